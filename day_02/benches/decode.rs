@@ -0,0 +1,69 @@
+use day_02::asm::assemble;
+use day_02::{BigInt, Compiled, StopReason, Vm, Word};
+use std::time::{Duration, Instant};
+
+//Compares running a hot loop with and without the decode cache, and compiled. Run with `cargo bench`.
+
+const LOOP: &str = "
+    loop:   ADD [n], -1 -> [n]
+            MUL [n], 2 -> [tmp]
+            LT [tmp], 0 -> [tmp]
+            EQ [tmp], 1 -> [tmp]
+            JT [n], loop
+            OUT [n]
+            HLT
+    n:      .data 2000000
+    tmp:    .data 0
+";
+
+#[derive(Clone, Copy)]
+enum Mode {
+    Naive,
+    Cached,
+    Compiled,
+}
+
+//The best of a few runs, which is the one least disturbed by whatever else the machine was doing
+fn time<W: Word>(program: &[W], mode: Mode) -> Duration {
+    let compiled = Compiled::new(program);
+    (0..5)
+        .map(|_| {
+            let mut vm = Vm::new(program.to_vec());
+            if let Mode::Cached = mode {
+                vm = vm.with_decode_cache();
+            }
+            let start = Instant::now();
+            let state = match mode {
+                Mode::Compiled => compiled.run(&mut vm),
+                _ => vm.run(),
+            }
+            .expect("The benchmark crashed!");
+            let elapsed = start.elapsed();
+            assert_eq!(state.reason, StopReason::Output(W::from_i64(0)));
+            elapsed
+        })
+        .min()
+        .unwrap()
+}
+
+fn compare<W: Word>(name: &str) {
+    let program: Vec<W> = assemble(LOOP).expect("The benchmark doesn't assemble");
+    let naive = time(&program, Mode::Naive);
+    let cached = time(&program, Mode::Cached);
+    let compiled = time(&program, Mode::Compiled);
+    println!(
+        "{:>6}: naive {:>8.1?}, cached {:>8.1?} ({:.2}x), compiled {:>8.1?} ({:.2}x)",
+        name,
+        naive,
+        cached,
+        naive.as_secs_f64() / cached.as_secs_f64(),
+        compiled,
+        naive.as_secs_f64() / compiled.as_secs_f64()
+    );
+}
+
+fn main() {
+    compare::<i64>("i64");
+    compare::<i128>("i128");
+    compare::<BigInt>("BigInt");
+}
@@ -0,0 +1,207 @@
+use crate::cfg::Cfg;
+use crate::error::VmError;
+use crate::instruction::{IntcodeInstruction, Param, ParamMode};
+use crate::vm::{StepRecord, StopReason, Vm};
+use crate::word::Word;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+//Works out what each cell of a program is for: instructions, their operands, or data they read and write.
+// The static part follows the control-flow graph from address 0, and only sees addresses that are constant.
+// Running the program (or some of it) fills in what that missed: computed jumps, relative addresses, and
+// everything self-modifying code gets up to.
+
+//The main thing a cell is used as
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CellKind {
+    //The first word of an instruction that can be reached
+    Code,
+    //Any other word of one
+    Operand,
+    DataRead,
+    DataWritten,
+    //Nothing we know of touches it
+    Unknown,
+}
+
+//Everything a cell was found to be used as. It can be more than one thing: day 2 writes over operands of code
+// it has already run, for instance.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Usage {
+    pub code: bool,
+    pub operand: bool,
+    pub read: bool,
+    pub written: bool,
+}
+
+impl Usage {
+    //Code wins over operands, which win over data. Data that's written to is more interesting than data that's
+    // only read, since that's where results go.
+    pub fn kind(&self) -> CellKind {
+        if self.code {
+            CellKind::Code
+        } else if self.operand {
+            CellKind::Operand
+        } else if self.written {
+            CellKind::DataWritten
+        } else if self.read {
+            CellKind::DataRead
+        } else {
+            CellKind::Unknown
+        }
+    }
+}
+
+//Something like `operand, written`
+impl fmt::Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let uses = [
+            (self.code, "code"),
+            (self.operand, "operand"),
+            (self.read, "read"),
+            (self.written, "written"),
+        ];
+        let uses: Vec<&str> = uses
+            .iter()
+            .filter(|(is, _)| *is)
+            .map(|(_, name)| *name)
+            .collect();
+        match uses.is_empty() {
+            true => write!(f, "unknown"),
+            false => write!(f, "{}", uses.join(", ")),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Analysis {
+    //Cells nothing was found about aren't in there
+    cells: BTreeMap<usize, Usage>,
+}
+
+impl Analysis {
+    //Everything that can be found without running the program
+    pub fn new<W: Word>(program: &[W]) -> Self {
+        let mut analysis = Analysis::default();
+        for block in Cfg::new(program).blocks.values() {
+            for (address, instruction) in &block.instructions {
+                analysis.instruction(*address, instruction);
+            }
+        }
+        analysis
+    }
+
+    pub fn get(&self, address: usize) -> Usage {
+        self.cells.get(&address).cloned().unwrap_or_default()
+    }
+
+    pub fn kind(&self, address: usize) -> CellKind {
+        self.get(address).kind()
+    }
+
+    fn mark(&mut self, address: usize, how: impl FnOnce(&mut Usage)) {
+        how(self.cells.entry(address).or_default())
+    }
+
+    //An instruction at an address, and whatever constant addresses it reads from or writes to
+    fn instruction<W: Word>(&mut self, address: usize, instruction: &IntcodeInstruction<W>) {
+        self.mark(address, |usage| usage.code = true);
+        for operand in address + 1..address + instruction.size() {
+            self.mark(operand, |usage| usage.operand = true);
+        }
+        let constant = |param: &Param<W>| match param.mode {
+            ParamMode::Position => param.value.to_i64().and_then(|a| usize::try_from(a).ok()),
+            _ => None,
+        };
+        let (sources, dest) = match instruction {
+            IntcodeInstruction::Add { lhs, rhs, dest }
+            | IntcodeInstruction::Mul { lhs, rhs, dest }
+            | IntcodeInstruction::LessThan { lhs, rhs, dest }
+            | IntcodeInstruction::Equals { lhs, rhs, dest } => (vec![lhs, rhs], Some(dest)),
+            IntcodeInstruction::JumpIfTrue { cond, target }
+            | IntcodeInstruction::JumpIfFalse { cond, target } => (vec![cond, target], None),
+            IntcodeInstruction::Output { src: param }
+            | IntcodeInstruction::AdjustBase { offset: param } => (vec![param], None),
+            IntcodeInstruction::Input { dest } => (vec![], Some(dest)),
+            IntcodeInstruction::Halt => (vec![], None),
+        };
+        for address in sources.into_iter().filter_map(constant) {
+            self.mark(address, |usage| usage.read = true);
+        }
+        if let Some(address) = dest.and_then(constant) {
+            self.mark(address, |usage| usage.written = true);
+        }
+    }
+
+    //Adds what an executed instruction actually did
+    pub fn record<W: Word>(&mut self, record: &StepRecord<W>) {
+        self.mark(record.ip, |usage| usage.code = true);
+        for operand in record.ip + 1..record.ip + record.instruction.size() {
+            self.mark(operand, |usage| usage.operand = true);
+        }
+        for &address in &record.reads {
+            self.mark(address, |usage| usage.read = true);
+        }
+        if let Some(write) = &record.write {
+            self.mark(write.address, |usage| usage.written = true);
+        }
+    }
+
+    //Runs a machine until it halts or needs input, recording what it does along the way. Outputs are passed over.
+    // Giving the machine a step limit is a good idea, in case the program never stops.
+    pub fn refine<W: Word>(&mut self, vm: &mut Vm<W>) -> Result<StopReason<W>, VmError<W>> {
+        loop {
+            let (stop, record) = vm.step_recorded()?;
+            if let Some(record) = record {
+                self.record(&record);
+            }
+            match stop {
+                Some(StopReason::Output(_)) | Some(StopReason::Watchpoint(_)) | None => {}
+                Some(reason) => return Ok(reason),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::*;
+
+    #[test]
+    fn day_02_example() {
+        let program = [1i64, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50, 2, 14, 0, 0];
+        let analysis = Analysis::new(&program);
+        let kinds: Vec<CellKind> = (0..program.len()).map(|a| analysis.kind(a)).collect();
+        use CellKind::*;
+        assert_eq!(
+            kinds,
+            vec![
+                Code, Operand, Operand, Operand, Code, Operand, Operand, Operand, Code, DataRead,
+                DataRead, DataRead, Unknown, Unknown, Unknown, Unknown
+            ]
+        );
+        //The first instruction writes over one of the second one's operands
+        assert_eq!(analysis.get(3).to_string(), "operand, read, written");
+        assert_eq!(analysis.get(0).to_string(), "code, written");
+    }
+
+    #[test]
+    fn refined_by_running() {
+        //Jumps to the address in cell 9 (which static analysis can't follow), to output [rb+12]
+        let program = vec![109i64, 2, 106, 0, 9, 99, 99, 99, 0, 10, 204, 12, 99, 0, 7];
+        let mut analysis = Analysis::new(&program);
+        assert_eq!(analysis.kind(10), CellKind::Unknown);
+        assert_eq!(analysis.kind(9), CellKind::DataRead);
+        let mut vm = Vm::new(program).with_step_limit(100);
+        assert_eq!(analysis.refine(&mut vm), Ok(StopReason::Halted));
+        assert_eq!(analysis.kind(10), CellKind::Code);
+        assert_eq!(analysis.kind(11), CellKind::Operand);
+        assert_eq!(analysis.kind(12), CellKind::Code);
+        assert_eq!(analysis.kind(13), CellKind::Unknown);
+        assert_eq!(analysis.kind(14), CellKind::DataRead);
+        //Never reached either way
+        assert_eq!(analysis.kind(5), CellKind::Unknown);
+        assert_eq!(analysis.kind(8), CellKind::Unknown);
+    }
+}
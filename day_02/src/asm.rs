@@ -0,0 +1,399 @@
+use crate::instruction::ParamMode;
+use crate::word::Word;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+//A tiny assembly language for Intcode, which reads just like the disassembler's listings:
+//
+//  ; Comments start with a semicolon
+//  loop:   IN -> [value]           ; position mode is [address]
+//          ADD [value], -1 -> [rb+2]  ; relative mode is [rb+offset]
+//          JT [value], loop        ; immediate mode is the bare value, and labels are addresses
+//          HLT
+//  value:  .data 0                 ; raw words, separated by commas
+//
+//Mnemonics are case-insensitive, labels can be used before they're defined, and `label+3`/`label-3` works anywhere
+// a label does. The `0000:` addresses and `DATA` lines of a listing are accepted too, so listings can be fed back in.
+
+//Where (1-based) and why assembling failed
+#[derive(Clone, Debug, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+//A number, or a label (plus some offset) that we'll only know the address of once everything's been read
+#[derive(Debug)]
+enum Expr<W> {
+    Number(W),
+    Label { name: String, offset: i64 },
+}
+
+//Some source text, and where it was (so errors can point at it)
+#[derive(Clone, Copy)]
+struct Span<'a> {
+    text: &'a str,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Span<'a> {
+    fn error(&self, message: impl Into<String>) -> AsmError {
+        AsmError {
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        }
+    }
+
+    //Leading and trailing whitespace doesn't count, but the column should still point at the text
+    fn trim(self) -> Self {
+        let start = self.text.len() - self.text.trim_start().len();
+        Span {
+            text: self.text.trim(),
+            column: self.column + start,
+            ..self
+        }
+    }
+
+    fn slice(self, start: usize, end: usize) -> Self {
+        Span {
+            text: &self.text[start..end],
+            column: self.column + start,
+            ..self
+        }
+    }
+
+    //Splits on every `separator`, keeping track of where each piece starts
+    fn split(self, separator: &'a str) -> impl Iterator<Item = Span<'a>> + 'a {
+        let mut start = 0;
+        self.text.split(separator).map(move |piece| {
+            let span = Span {
+                text: piece,
+                column: self.column + start,
+                ..self
+            };
+            start += piece.len() + separator.len();
+            span
+        })
+    }
+}
+
+//One word of output, waiting for its labels to be resolved
+struct Pending<'a, W> {
+    expr: Expr<W>,
+    span: Span<'a>,
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_expr<W: Word>(span: Span) -> Result<Expr<W>, AsmError> {
+    if let Ok(number) = span.text.parse() {
+        return Ok(Expr::Number(number));
+    }
+    //label, label+offset or label-offset
+    let (name, offset) = match span.text.find(['+', '-']) {
+        Some(at) => {
+            let offset = span.text[at..].trim_start_matches('+').trim();
+            let offset = offset
+                .parse()
+                .map_err(|_| span.error(format!("invalid offset `{}`", &span.text[at..])))?;
+            (span.text[..at].trim(), offset)
+        }
+        None => (span.text, 0),
+    };
+    if !is_identifier(name) {
+        return Err(span.error(format!(
+            "expected a number or a label, found `{}`",
+            span.text
+        )));
+    }
+    Ok(Expr::Label {
+        name: name.to_string(),
+        offset,
+    })
+}
+
+fn parse_operand<W: Word>(span: Span) -> Result<(ParamMode, Expr<W>), AsmError> {
+    let span = span.trim();
+    if span.text.is_empty() {
+        return Err(span.error("missing operand"));
+    }
+    if !span.text.starts_with('[') {
+        return Ok((ParamMode::Immediate, parse_expr(span)?));
+    }
+    if !span.text.ends_with(']') {
+        return Err(span.error("missing `]`"));
+    }
+    let inner = span.slice(1, span.text.len() - 1).trim();
+    match inner.text.strip_prefix("rb") {
+        Some(offset) if offset.starts_with(['+', '-']) => {
+            //[rb-3] is a negative number, [rb+3] or [rb+label] is whatever comes after the plus
+            let offset = inner.slice(2, inner.text.len());
+            let offset = if offset.text.starts_with('+') {
+                offset.slice(1, offset.text.len()).trim()
+            } else {
+                offset
+            };
+            Ok((ParamMode::Relative, parse_expr(offset)?))
+        }
+        _ => Ok((ParamMode::Position, parse_expr(inner)?)),
+    }
+}
+
+//Opcode, how many source operands, and whether there's a destination
+fn signature(mnemonic: &str) -> Option<(i64, usize, bool)> {
+    Some(match mnemonic.to_ascii_uppercase().as_str() {
+        "ADD" => (1, 2, true),
+        "MUL" => (2, 2, true),
+        "IN" => (3, 0, true),
+        "OUT" => (4, 1, false),
+        "JT" => (5, 2, false),
+        "JF" => (6, 2, false),
+        "LT" => (7, 2, true),
+        "EQ" => (8, 2, true),
+        "ARB" => (9, 1, false),
+        "HLT" => (99, 0, false),
+        _ => return None,
+    })
+}
+
+//Everything after the mnemonic: `src, src -> dest`
+fn parse_instruction<'a, W: Word>(
+    mnemonic: Span<'a>,
+    rest: Span<'a>,
+    output: &mut Vec<Pending<'a, W>>,
+) -> Result<(), AsmError> {
+    let (opcode, sources, has_dest) = signature(mnemonic.text)
+        .ok_or_else(|| mnemonic.error(format!("unknown mnemonic `{}`", mnemonic.text)))?;
+    let mut parts = rest.split("->");
+    let source_span = parts.next().unwrap().trim();
+    let dest_span = parts.next();
+    if let Some(extra) = parts.next() {
+        return Err(extra.error("only one `->` is allowed"));
+    }
+
+    let mut operands = Vec::new();
+    if !source_span.text.is_empty() {
+        for span in source_span.split(",") {
+            operands.push((parse_operand(span)?, span.trim()));
+        }
+    }
+    if operands.len() != sources {
+        return Err(source_span.error(format!(
+            "{} takes {} source operand(s), found {}",
+            mnemonic.text.to_ascii_uppercase(),
+            sources,
+            operands.len()
+        )));
+    }
+    match (dest_span, has_dest) {
+        (Some(span), true) => {
+            let ((mode, expr), span) = (parse_operand(span)?, span.trim());
+            if mode == ParamMode::Immediate {
+                return Err(span.error("destinations can't be immediate"));
+            }
+            operands.push(((mode, expr), span));
+        }
+        (None, true) => return Err(rest.error("missing destination (`-> [address]`)")),
+        (Some(span), false) => {
+            return Err(span.error(format!(
+                "{} doesn't take a destination",
+                mnemonic.text.to_ascii_uppercase()
+            )))
+        }
+        (None, false) => {}
+    }
+
+    //The first word is the opcode, with one mode digit per operand
+    let word = operands
+        .iter()
+        .enumerate()
+        .fold(opcode, |word, (n, ((mode, _), _))| {
+            let digit = match mode {
+                ParamMode::Position => 0,
+                ParamMode::Immediate => 1,
+                ParamMode::Relative => 2,
+            };
+            word + digit * 10i64.pow(n as u32 + 2)
+        });
+    output.push(Pending {
+        expr: Expr::Number(W::from_i64(word)),
+        span: mnemonic,
+    });
+    for ((_, expr), span) in operands {
+        output.push(Pending { expr, span });
+    }
+    Ok(())
+}
+
+fn parse_line<'a, W: Word>(
+    line: Span<'a>,
+    labels: &mut HashMap<&'a str, (usize, Span<'a>)>,
+    output: &mut Vec<Pending<'a, W>>,
+) -> Result<(), AsmError> {
+    let end = line.text.find(';').unwrap_or(line.text.len());
+    let mut line = line.slice(0, end).trim();
+
+    //Labels (and listing addresses) come first, each followed by a colon
+    while let Some(colon) = line.text.find(':') {
+        let label = line.slice(0, colon).trim();
+        if is_identifier(label.text) {
+            if let Some((_, previous)) = labels.insert(label.text, (output.len(), label)) {
+                return Err(label.error(format!(
+                    "label `{}` is already defined on line {}",
+                    label.text, previous.line
+                )));
+            }
+        } else if !label.text.bytes().all(|b| b.is_ascii_digit()) {
+            break;
+        }
+        line = line.slice(colon + 1, line.text.len()).trim();
+    }
+    if line.text.is_empty() {
+        return Ok(());
+    }
+
+    let split = line
+        .text
+        .find(char::is_whitespace)
+        .unwrap_or(line.text.len());
+    let mnemonic = line.slice(0, split);
+    let rest = line.slice(split, line.text.len());
+    if mnemonic.text == ".data" || mnemonic.text.eq_ignore_ascii_case("DATA") {
+        if rest.text.trim().is_empty() {
+            return Err(mnemonic.error("`.data` needs at least one value"));
+        }
+        for span in rest.split(",") {
+            let span = span.trim();
+            output.push(Pending {
+                expr: parse_expr(span)?,
+                span,
+            });
+        }
+        Ok(())
+    } else if mnemonic.text.starts_with('.') {
+        Err(mnemonic.error(format!("unknown directive `{}`", mnemonic.text)))
+    } else {
+        parse_instruction(mnemonic, rest, output)
+    }
+}
+
+//Turns assembly source into an Intcode program
+pub fn assemble<W: Word>(source: &str) -> Result<Vec<W>, AsmError> {
+    let mut labels = HashMap::new();
+    let mut pending = Vec::new();
+    for (n, text) in source.lines().enumerate() {
+        let line = Span {
+            text,
+            line: n + 1,
+            column: 1,
+        };
+        parse_line(line, &mut labels, &mut pending)?;
+    }
+
+    //Now that every label has an address, fill in the blanks
+    pending
+        .into_iter()
+        .map(|Pending { expr, span }| match expr {
+            Expr::Number(value) => Ok(value),
+            Expr::Label { name, offset } => {
+                let &(address, _) = labels
+                    .get(name.as_str())
+                    .ok_or_else(|| span.error(format!("undefined label `{}`", name)))?;
+                i64::try_from(address)
+                    .ok()
+                    .and_then(|address| address.checked_add(offset))
+                    .map(W::from_i64)
+                    .ok_or_else(|| span.error("label offset out of range"))
+            }
+        })
+        .collect()
+}
+
+//The usual comma-separated format puzzle inputs come in
+pub fn format_program<W: Word>(program: &[W]) -> String {
+    let words: Vec<String> = program.iter().map(|word| word.to_string()).collect();
+    words.join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::asm::*;
+    use crate::disasm::disassemble;
+
+    #[test]
+    fn labels_and_modes() {
+        let source = "
+            ; Counts down from whatever it's given
+            start:  IN -> [counter]
+            loop:   OUT [counter]
+                    ADD [counter], -1 -> [counter]
+                    JT [counter], loop
+                    ARB 3
+                    OUT [rb-3]    ; that's the first word of the program
+                    HLT
+            counter: .data 0, start+1
+        ";
+        let program: Vec<i64> = assemble(source).unwrap();
+        assert_eq!(
+            format_program(&program),
+            "3,16,4,16,1001,16,-1,16,1005,16,2,109,3,204,-3,99,0,1"
+        );
+    }
+
+    #[test]
+    fn listings_round_trip() {
+        let program = vec![
+            21101i64, 1, -2, 3, 3, 7, 204, -1, 1005, 7, 0, 2107, 5, 6, 7, 99, 30, 40,
+        ];
+        let listing = disassemble(&program);
+        assert_eq!(assemble::<i64>(&listing).unwrap(), program);
+    }
+
+    #[test]
+    fn errors_have_positions() {
+        let error = |source| assemble::<i64>(source).unwrap_err();
+        assert_eq!(
+            error("HLT\n  FOO 1"),
+            AsmError {
+                line: 2,
+                column: 3,
+                message: "unknown mnemonic `FOO`".to_string()
+            }
+        );
+        assert_eq!(error("ADD 1, 2 -> 3").column, 13);
+        assert_eq!(error("JT 1, nowhere").message, "undefined label `nowhere`");
+        assert_eq!(
+            error("ADD 1 -> [0]").message,
+            "ADD takes 2 source operand(s), found 1"
+        );
+        assert_eq!(
+            error("a: HLT\na: HLT").message,
+            "label `a` is already defined on line 1"
+        );
+        assert_eq!(error("OUT [rb+x").column, 5);
+        assert_eq!(
+            error("HLT\nb: JT 1, b+9223372036854775807"),
+            AsmError {
+                line: 2,
+                column: 10,
+                message: "label offset out of range".to_string()
+            }
+        );
+    }
+}
@@ -0,0 +1,25 @@
+use day_02::asm::{assemble, format_program};
+use std::io::Read;
+
+//Assembles the file given as argument (or stdin, if there's none or it's `-`),
+// and prints the program in the usual comma-separated format
+fn main() {
+    let source = match std::env::args().nth(1) {
+        Some(path) if path != "-" => std::fs::read_to_string(path),
+        _ => {
+            let mut source = String::new();
+            std::io::stdin().read_to_string(&mut source).map(|_| source)
+        }
+    };
+    let source = source.unwrap_or_else(|error| {
+        eprintln!("error: couldn't read the source: {}", error);
+        std::process::exit(1);
+    });
+    match assemble::<i64>(&source) {
+        Ok(program) => println!("{}", format_program(&program)),
+        Err(error) => {
+            eprintln!("error: {}", error);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,13 @@
+use day_02::cfg::Cfg;
+use day_02::load_program;
+
+//Prints the control-flow graph of the Intcode program in the file given as argument (or on stdin, if there's
+// none or it's `-`), in Graphviz's format: `cfg input.txt | dot -Tsvg > cfg.svg`
+fn main() {
+    let path = std::env::args().nth(1).unwrap_or_else(|| "-".to_string());
+    let program: Vec<i64> = load_program(&path).unwrap_or_else(|error| {
+        eprintln!("error: {}", error);
+        std::process::exit(1);
+    });
+    print!("{}", Cfg::new(&program).to_dot());
+}
@@ -0,0 +1,37 @@
+use day_02::debugger::Debugger;
+use day_02::load_program;
+use day_02::vm::Vm;
+use std::io::{BufRead, Write};
+
+//An interactive debugger for the Intcode program in the file given as argument.
+// Type `help` at the prompt for a list of commands.
+fn main() {
+    let path = match std::env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: debugger <program>");
+            std::process::exit(1);
+        }
+    };
+    let program: Vec<i64> = load_program(&path).unwrap_or_else(|error| {
+        eprintln!("error: {}", error);
+        std::process::exit(1);
+    });
+
+    let mut debugger = Debugger::new(Vm::new(program));
+    println!("{}", debugger.describe(0));
+    let stdin = std::io::stdin();
+    loop {
+        print!("(dbg) ");
+        std::io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            break;
+        }
+        match debugger.command(&line) {
+            Some(reply) if reply.is_empty() => {}
+            Some(reply) => println!("{}", reply),
+            None => break,
+        }
+    }
+}
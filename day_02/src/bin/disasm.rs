@@ -0,0 +1,16 @@
+use day_02::disasm::annotate;
+use day_02::{load_program, Analysis, Vm};
+
+//Prints a listing of the Intcode program in the file given as argument (or on stdin, if there's none or it's `-`).
+// The program gets a test run first (without any input), so that code only reached at runtime shows up as such.
+fn main() {
+    let path = std::env::args().nth(1).unwrap_or_else(|| "-".to_string());
+    let program: Vec<i64> = load_program(&path).unwrap_or_else(|error| {
+        eprintln!("error: {}", error);
+        std::process::exit(1);
+    });
+    let mut analysis = Analysis::new(&program);
+    //Wherever the run stops, what it did until then is still worth knowing
+    let _ = analysis.refine(&mut Vm::new(program.clone()).with_step_limit(1_000_000));
+    print!("{}", annotate(&program, &analysis));
+}
@@ -0,0 +1,230 @@
+use crate::error::VmErrorKind;
+use crate::instruction::{IntcodeInstruction, ParamMode};
+use crate::word::Word;
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+//Splits a program into basic blocks: runs of instructions that always execute one after the other, starting
+// at address 0 and following every jump whose target we can tell without running anything.
+// Jumps to an address held in memory (or relative to the base) are computed, and get an unknown edge.
+// This looks at the program as it is, so code that rewrites itself can do things the graph doesn't show.
+
+//Where control can go after a block
+#[derive(Clone, Debug, PartialEq)]
+pub enum Edge {
+    //On to the next instruction, which starts another block
+    Next(usize),
+    //A jump with a constant target
+    Jump(usize),
+    //A jump to somewhere that's only known at runtime
+    Unknown,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block<W = i64> {
+    pub start: usize,
+    //Every instruction in the block, with its address
+    pub instructions: Vec<(usize, IntcodeInstruction<W>)>,
+    //Set when the block ends in something that doesn't decode, which would crash the program
+    pub error: Option<VmErrorKind<W>>,
+    pub edges: Vec<Edge>,
+}
+
+impl<W: Word> Block<W> {
+    //One past the last word of the block
+    pub fn end(&self) -> usize {
+        match self.instructions.last() {
+            Some((address, instruction)) => address + instruction.size(),
+            None => self.start,
+        }
+    }
+}
+
+//Every block, by start address
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cfg<W = i64> {
+    pub blocks: BTreeMap<usize, Block<W>>,
+}
+
+//Where an instruction can send us next, and whether it's the last one of its block
+fn successors<W: Word>(address: usize, instruction: &IntcodeInstruction<W>) -> (Vec<Edge>, bool) {
+    let next = address + instruction.size();
+    let (cond, target, when) = match instruction {
+        IntcodeInstruction::Halt => return (Vec::new(), true),
+        IntcodeInstruction::JumpIfTrue { cond, target } => (cond, target, true),
+        IntcodeInstruction::JumpIfFalse { cond, target } => (cond, target, false),
+        _ => return (vec![Edge::Next(next)], false),
+    };
+    //A constant condition means the jump is always (`JT 1, x`) or never taken
+    let (jumps, falls) = match cond.mode {
+        ParamMode::Immediate if cond.value.is_zero() != when => (true, false),
+        ParamMode::Immediate => (false, true),
+        _ => (true, true),
+    };
+    let mut edges = Vec::new();
+    if jumps {
+        let constant = match target.mode {
+            ParamMode::Immediate => target.value.to_i64().and_then(|t| usize::try_from(t).ok()),
+            _ => None,
+        };
+        edges.push(constant.map_or(Edge::Unknown, Edge::Jump));
+    }
+    if falls {
+        edges.push(Edge::Next(next));
+    }
+    (edges, true)
+}
+
+impl<W: Word> Cfg<W> {
+    pub fn new(program: &[W]) -> Self {
+        let decode =
+            |address: usize| IntcodeInstruction::new(program.get(address..).unwrap_or(&[]));
+        //Find every instruction that can be reached, and where blocks have to start
+        let mut leaders = BTreeSet::new();
+        let mut seen = BTreeSet::new();
+        let mut pending = vec![0];
+        leaders.insert(0);
+        while let Some(address) = pending.pop() {
+            if !seen.insert(address) {
+                continue;
+            }
+            let instruction = match decode(address) {
+                Ok(instruction) => instruction,
+                Err(_) => continue,
+            };
+            let (edges, last) = successors(address, &instruction);
+            for edge in edges {
+                match edge {
+                    Edge::Jump(to) => {
+                        leaders.insert(to);
+                        pending.push(to);
+                    }
+                    Edge::Next(to) => {
+                        //Whatever comes after a jump can be jumped over, so it's a block of its own
+                        if last {
+                            leaders.insert(to);
+                        }
+                        pending.push(to);
+                    }
+                    Edge::Unknown => {}
+                }
+            }
+        }
+
+        let blocks = leaders
+            .iter()
+            .map(|&start| {
+                let mut block = Block {
+                    start,
+                    instructions: Vec::new(),
+                    error: None,
+                    edges: Vec::new(),
+                };
+                let mut address = start;
+                loop {
+                    let instruction = match decode(address) {
+                        Ok(instruction) => instruction,
+                        Err(error) => {
+                            block.error = Some(error);
+                            break;
+                        }
+                    };
+                    let (edges, last) = successors(address, &instruction);
+                    let next = address + instruction.size();
+                    block.instructions.push((address, instruction));
+                    address = next;
+                    if last || leaders.contains(&address) {
+                        block.edges = edges;
+                        break;
+                    }
+                }
+                (start, block)
+            })
+            .collect();
+        Cfg { blocks }
+    }
+
+    //The graph in Graphviz's format, with each block's listing in its box. Pipe it through `dot -Tsvg` to see it.
+    // Jumps are labelled, and unknown edges are dashed and go to a `?`.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph cfg {\n    node [shape=box, fontname=monospace];\n");
+        let mut unknown = false;
+        for block in self.blocks.values() {
+            let mut label = String::new();
+            for (address, instruction) in &block.instructions {
+                write!(label, "{:04}: {}\\l", address, instruction).unwrap();
+            }
+            if let Some(error) = &block.error {
+                write!(label, "{:04}: ({})\\l", block.end(), error).unwrap();
+            }
+            writeln!(dot, "    b{} [label=\"{}\"];", block.start, label).unwrap();
+            for edge in &block.edges {
+                match edge {
+                    Edge::Next(to) => writeln!(dot, "    b{} -> b{};", block.start, to),
+                    Edge::Jump(to) => {
+                        writeln!(dot, "    b{} -> b{} [label=\"jump\"];", block.start, to)
+                    }
+                    Edge::Unknown => {
+                        unknown = true;
+                        writeln!(dot, "    b{} -> unknown [style=dashed];", block.start)
+                    }
+                }
+                .unwrap();
+            }
+        }
+        if unknown {
+            dot.push_str("    unknown [shape=diamond, label=\"?\"];\n");
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cfg::*;
+    use crate::fixtures::AROUND_8;
+
+    #[test]
+    fn straight_line() {
+        let cfg = Cfg::new(&[1i64, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+        assert_eq!(cfg.blocks.len(), 1);
+        let block = &cfg.blocks[&0];
+        assert_eq!((block.instructions.len(), block.end()), (3, 9));
+        assert_eq!(block.edges, vec![]);
+    }
+
+    #[test]
+    fn branches() {
+        let cfg = Cfg::new(&AROUND_8);
+        let starts: Vec<usize> = cfg.blocks.keys().cloned().collect();
+        assert_eq!(starts, vec![0, 9, 16, 22, 31, 36, 46]);
+        assert_eq!(cfg.blocks[&0].edges, vec![Edge::Jump(22), Edge::Next(9)]);
+        //`JF 0, 36` always jumps, so there's nothing after it
+        assert_eq!(cfg.blocks[&16].edges, vec![Edge::Jump(36)]);
+        assert_eq!(cfg.blocks[&46].edges, vec![]);
+    }
+
+    #[test]
+    fn dot() {
+        //Jumps to itself while the input isn't zero, then to wherever cell 0 says
+        let cfg = Cfg::new(&[3i64, 8, 1005, 8, 0, 106, 0, 0, 0]);
+        assert_eq!(
+            cfg.to_dot(),
+            r#"digraph cfg {
+    node [shape=box, fontname=monospace];
+    b0 [label="0000: IN -> [8]\l0002: JT [8], 0\l"];
+    b0 -> b0 [label="jump"];
+    b0 -> b5;
+    b5 [label="0005: JF 0, [0]\l"];
+    b5 -> unknown [style=dashed];
+    unknown [shape=diamond, label="?"];
+}
+"#
+        );
+        //Running off into something that isn't code
+        let cfg = Cfg::new(&[1105i64, 1, 3, 42]);
+        assert_eq!(cfg.blocks[&3].error, Some(VmErrorKind::UnknownOpcode(42)));
+    }
+}
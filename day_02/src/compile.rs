@@ -0,0 +1,281 @@
+use crate::error::VmError;
+use crate::instruction::{IntcodeInstruction, Param, ParamMode};
+use crate::memory::{Memory, DENSE_LIMIT};
+use crate::vm::{overlapping, RunState, StopReason, Vm};
+use crate::word::Word;
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+//A program turned into something quicker to run than raw words, for when the same program runs over and over.
+// Every operand is worked out once, up front: addresses are plain usizes, and immediates are ready to use.
+// Running checks what the machine's memory actually holds against what was compiled, so patching the program
+// (or the program patching itself) is fine: anything that changed goes through the interpreter instead.
+// The machine keeps track of what changed from then on, so that only happens once, not on every run.
+
+//Tells compiled programs apart, so machines know which one their stale ops are about
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+//Where an operand's value comes from
+#[derive(Clone, Debug)]
+enum Operand<W> {
+    Value(W),
+    Cell(usize),
+    Relative(i64),
+}
+
+#[derive(Clone, Debug)]
+enum Op<W> {
+    Add(Operand<W>, Operand<W>, Operand<W>),
+    Mul(Operand<W>, Operand<W>, Operand<W>),
+    LessThan(Operand<W>, Operand<W>, Operand<W>),
+    Equals(Operand<W>, Operand<W>, Operand<W>),
+    JumpIfTrue(Operand<W>, Operand<W>),
+    JumpIfFalse(Operand<W>, Operand<W>),
+    AdjustBase(Operand<W>),
+    Output(Operand<W>),
+    Halt,
+}
+
+//An op, and where the next one is unless it jumps
+#[derive(Clone, Debug)]
+struct Entry<W> {
+    op: Op<W>,
+    next: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Compiled<W = i64> {
+    id: u64,
+    template: Memory<W>,
+    //One op for every address the program decodes at, since jumps can land anywhere.
+    // Input isn't in there: it's rare, and the interpreter already knows how to wait for it.
+    ops: Vec<Option<Entry<W>>>,
+}
+
+impl<W: Word> Compiled<W> {
+    pub fn new(program: impl Into<Memory<W>>) -> Self {
+        let template = program.into();
+        let ops = (0..template.len().min(DENSE_LIMIT))
+            .map(|address| {
+                let instruction = IntcodeInstruction::new(&template.fetch(address)).ok()?;
+                let next = address + instruction.size();
+                Some(Entry {
+                    op: compile(instruction)?,
+                    next,
+                })
+            })
+            .collect();
+        Compiled {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            template,
+            ops,
+        }
+    }
+
+    //A fresh machine, loaded with the program as it was compiled (so nothing's stale yet)
+    pub fn vm(&self) -> Vm<W> {
+        let mut vm = Vm::new(self.template.clone());
+        vm.stale = Some((self.id, vec![false; self.ops.len()]));
+        vm
+    }
+
+    //Does what `Vm::run` does, only quicker. Machines keeping an undo log, looking for loops or watching cells
+    // need the interpreter's bookkeeping at every step, so they're just handed to it.
+    pub fn run(&self, vm: &mut Vm<W>) -> Result<RunState<W>, VmError<W>> {
+        if vm.undo.is_some() || vm.loop_detection || !vm.watchpoints.is_empty() {
+            return vm.run();
+        }
+        if !matches!(&vm.stale, Some((id, _)) if *id == self.id) {
+            //Whatever doesn't match the template anymore can't be trusted. Ops near the end can reach a few
+            // cells past it.
+            let mut stale = vec![false; self.ops.len()];
+            for address in 0..self.ops.len() + 3 {
+                if vm.memory.get(address) != self.template.get(address) {
+                    for start in overlapping(address) {
+                        if let Some(entry) = stale.get_mut(start) {
+                            *entry = true;
+                        }
+                    }
+                }
+            }
+            vm.stale = Some((self.id, stale));
+        }
+        loop {
+            let limited = matches!(vm.step_limit, Some(limit) if vm.steps >= limit);
+            let stale = !matches!(&vm.stale, Some((_, stale)) if stale.get(vm.ip) == Some(&false));
+            let fast = match self.ops.get(vm.ip) {
+                Some(Some(entry)) if !vm.halted && !limited && !stale => execute(entry, vm),
+                _ => None,
+            };
+            let stop = match fast {
+                Some(stop) => stop,
+                //Errors come from here too: ops give up without touching anything when something's wrong,
+                // so the interpreter gets to run into the same problem and describe it properly
+                None => vm.step()?,
+            };
+            if let Some(reason) = stop {
+                return Ok(RunState {
+                    reason,
+                    ip: vm.ip,
+                    steps: vm.steps,
+                });
+            }
+        }
+    }
+}
+
+//None for operands that can't ever be valid (like negative addresses), which are left to the interpreter
+fn operand<W: Word>(param: Param<W>) -> Option<Operand<W>> {
+    match param.mode {
+        ParamMode::Immediate => Some(Operand::Value(param.value)),
+        ParamMode::Position => Some(Operand::Cell(usize::try_from(param.value.to_i64()?).ok()?)),
+        ParamMode::Relative => Some(Operand::Relative(param.value.to_i64()?)),
+    }
+}
+
+fn compile<W: Word>(instruction: IntcodeInstruction<W>) -> Option<Op<W>> {
+    Some(match instruction {
+        IntcodeInstruction::Add { lhs, rhs, dest } => {
+            Op::Add(operand(lhs)?, operand(rhs)?, operand(dest)?)
+        }
+        IntcodeInstruction::Mul { lhs, rhs, dest } => {
+            Op::Mul(operand(lhs)?, operand(rhs)?, operand(dest)?)
+        }
+        IntcodeInstruction::LessThan { lhs, rhs, dest } => {
+            Op::LessThan(operand(lhs)?, operand(rhs)?, operand(dest)?)
+        }
+        IntcodeInstruction::Equals { lhs, rhs, dest } => {
+            Op::Equals(operand(lhs)?, operand(rhs)?, operand(dest)?)
+        }
+        IntcodeInstruction::JumpIfTrue { cond, target } => {
+            Op::JumpIfTrue(operand(cond)?, operand(target)?)
+        }
+        IntcodeInstruction::JumpIfFalse { cond, target } => {
+            Op::JumpIfFalse(operand(cond)?, operand(target)?)
+        }
+        IntcodeInstruction::AdjustBase { offset } => Op::AdjustBase(operand(offset)?),
+        IntcodeInstruction::Output { src } => Op::Output(operand(src)?),
+        IntcodeInstruction::Halt => Op::Halt,
+        IntcodeInstruction::Input { .. } => return None,
+    })
+}
+
+fn address<W>(vm: &Vm<W>, operand: &Operand<W>) -> Option<usize> {
+    match operand {
+        Operand::Cell(address) => Some(*address),
+        Operand::Relative(offset) => usize::try_from(vm.relative_base.checked_add(*offset)?).ok(),
+        Operand::Value(_) => None,
+    }
+}
+
+fn read<W: Word>(vm: &Vm<W>, operand: &Operand<W>) -> Option<W> {
+    match operand {
+        Operand::Value(value) => Some(value.clone()),
+        _ => Some(vm.memory.get(address(vm, operand)?)),
+    }
+}
+
+//Setting the cell is what marks whatever ops it's part of as stale
+fn write<W: Word>(vm: &mut Vm<W>, dest: &Operand<W>, value: W) -> Option<()> {
+    let address = address(vm, dest)?;
+    vm.set(address, value);
+    Some(())
+}
+
+//Runs one op. The outer None means it couldn't, and nothing was changed.
+fn execute<W: Word>(entry: &Entry<W>, vm: &mut Vm<W>) -> Option<Option<StopReason<W>>> {
+    let mut next = entry.next;
+    let mut stop = None;
+    let flag = |condition: bool| W::from_i64(condition as i64);
+    let target = |target: W| usize::try_from(target.to_i64()?).ok();
+    match &entry.op {
+        Op::Add(lhs, rhs, dest) => {
+            let value = read(vm, lhs)?.try_add(&read(vm, rhs)?)?;
+            write(vm, dest, value)?;
+        }
+        Op::Mul(lhs, rhs, dest) => {
+            let value = read(vm, lhs)?.try_mul(&read(vm, rhs)?)?;
+            write(vm, dest, value)?;
+        }
+        Op::LessThan(lhs, rhs, dest) => {
+            let value = flag(read(vm, lhs)? < read(vm, rhs)?);
+            write(vm, dest, value)?;
+        }
+        Op::Equals(lhs, rhs, dest) => {
+            let value = flag(read(vm, lhs)? == read(vm, rhs)?);
+            write(vm, dest, value)?;
+        }
+        Op::JumpIfTrue(cond, to) => {
+            if !read(vm, cond)?.is_zero() {
+                next = target(read(vm, to)?)?;
+            }
+        }
+        Op::JumpIfFalse(cond, to) => {
+            if read(vm, cond)?.is_zero() {
+                next = target(read(vm, to)?)?;
+            }
+        }
+        Op::AdjustBase(offset) => {
+            let offset = read(vm, offset)?.to_i64()?;
+            vm.relative_base = vm.relative_base.checked_add(offset)?;
+        }
+        Op::Output(src) => stop = Some(StopReason::Output(read(vm, src)?)),
+        Op::Halt => {
+            vm.halted = true;
+            next = vm.ip;
+            stop = Some(StopReason::Halted);
+        }
+    }
+    vm.ip = next;
+    vm.steps += 1;
+    Some(stop)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compile::Compiled;
+    use crate::fixtures::{same_compiled, AROUND_8, REWRITES_ITSELF};
+    use crate::vm::{StopReason, Vm};
+
+    #[test]
+    fn same_as_interpreter() {
+        let day_02 = vec![1i64, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50];
+        same_compiled(Vm::new(day_02), &[]);
+        for input in 7..=9 {
+            same_compiled(Vm::new(AROUND_8.to_vec()), &[input]);
+        }
+        //Relative base, and errors
+        same_compiled(Vm::new(vec![109i64, 5, 204, 2, 99, 0, 0, 42]), &[]);
+        same_compiled(Vm::new(vec![1, 0, 0, 0, 1102i64, i64::MAX, 2, 0, 99]), &[]);
+        same_compiled(Vm::new(vec![109i64, -10, 21101, 1, 1, 0, 99]), &[]);
+        same_compiled(
+            Vm::new(vec![1001i64, 7, 1, 7, 1105, 1, 0, 0]).with_step_limit(100),
+            &[],
+        );
+    }
+
+    #[test]
+    fn writes_to_code() {
+        same_compiled(Vm::new(REWRITES_ITSELF.to_vec()), &[]);
+
+        //Patched before running, the way day 2 does it: the first ADD reads other cells now
+        let program = Compiled::new(vec![1i64, 0, 0, 0, 4, 0, 99]);
+        let mut vm = program.vm();
+        vm.memory_mut().set(1, 4);
+        vm.memory_mut().set(2, 6);
+        assert_eq!(
+            program.run(&mut vm).unwrap().reason,
+            StopReason::Output(103)
+        );
+        //The same program still works untouched
+        let mut vm = program.vm();
+        assert_eq!(program.run(&mut vm).unwrap().reason, StopReason::Output(2));
+
+        //Patched between runs: `OUT [0]` turns into `OUT 0`
+        let program = Compiled::new(vec![104i64, 1, 4, 0, 99]);
+        let mut vm = program.vm();
+        assert_eq!(program.run(&mut vm).unwrap().reason, StopReason::Output(1));
+        vm.memory_mut().set(2, 104);
+        assert_eq!(program.run(&mut vm).unwrap().reason, StopReason::Output(0));
+    }
+}
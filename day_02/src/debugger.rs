@@ -0,0 +1,493 @@
+use crate::analysis::Analysis;
+use crate::instruction::IntcodeInstruction;
+use crate::memory::DENSE_LIMIT;
+use crate::vm::{Access, StopReason, Vm};
+use crate::word::Word;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+const HELP: &str = "\
+commands:
+  step [n]              execute n instructions (1 by default)
+  continue              run until a breakpoint, watchpoint, halt, error or input is needed
+  back [n]              take back n instructions (1 by default)
+  rewind <addr>         go back to just before the last write to addr
+  who <addr>            show which instruction last wrote to addr
+  break <addr>          set a breakpoint
+  clear <addr>          remove a breakpoint
+  breakpoints           list breakpoints
+  watch [addr] [len] [read|write|change]
+                        stop when len cells starting at addr are accessed that way (1 and write by default),
+                        or list watchpoints
+  unwatch <addr>        remove the watchpoints on addr
+  inst [addr]           show the instruction at addr (the IP by default)
+  read <addr> [len]     show len memory cells starting at addr (1 by default), and what they're used for
+  write <addr> <v>...   write values to memory, starting at addr (`set` works too)
+  input <v>...          queue up input values
+  regs                  show the IP, relative base and step count
+  quit";
+
+//The most cells `read` shows at once
+const MAX_READ: usize = 1000;
+
+//A debugger wrapped around a Vm. Commands go in as text, and whatever there is to say comes back out as text,
+// so that it can be driven by a REPL or scripted from tests.
+// The VM keeps an undo log, so we can go backwards too.
+pub struct Debugger<W = i64> {
+    vm: Vm<W>,
+    breakpoints: BTreeSet<usize>,
+    //What each cell is for, as far as we can tell from the program and what it's done so far
+    analysis: Analysis,
+}
+
+impl<W: Word> Debugger<W> {
+    pub fn new(vm: Vm<W>) -> Self {
+        let memory = vm.memory();
+        let program: Vec<W> = (0..memory.len().min(DENSE_LIMIT))
+            .map(|address| memory.get(address))
+            .collect();
+        Debugger {
+            vm: vm.with_undo_log(),
+            breakpoints: BTreeSet::new(),
+            analysis: Analysis::new(&program),
+        }
+    }
+
+    pub fn vm(&self) -> &Vm<W> {
+        &self.vm
+    }
+
+    //Runs one command line. Returns None when it's time to quit.
+    pub fn command(&mut self, line: &str) -> Option<String> {
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => return Some(String::new()),
+        };
+        let args: Vec<&str> = words.collect();
+        let result = match command {
+            "s" | "step" => self.step(&args),
+            "c" | "continue" => self.cont(),
+            "back" => self.back(&args),
+            "rewind" => self.rewind(&args),
+            "who" => self.who(&args),
+            "b" | "break" => self.set_breakpoint(&args, true),
+            "clear" => self.set_breakpoint(&args, false),
+            "breakpoints" => Ok(self.list_breakpoints()),
+            "w" | "watch" => self.watch(&args),
+            "unwatch" => self.unwatch(&args),
+            "i" | "inst" => self.inst(&args),
+            "x" | "read" => self.read(&args),
+            "set" | "write" => self.write(&args),
+            "input" => self.input(&args),
+            "regs" => Ok(self.regs()),
+            "h" | "help" => Ok(HELP.to_string()),
+            "q" | "quit" => return None,
+            _ => Err(format!("unknown command `{}` (try `help`)", command)),
+        };
+        Some(result.unwrap_or_else(|error| format!("error: {}", error)))
+    }
+
+    //The instruction at some address, as it would appear in a listing
+    pub fn describe(&self, address: usize) -> String {
+        match IntcodeInstruction::new(&self.vm.memory().fetch(address)) {
+            Ok(instruction) => format!("{:04}: {}", address, instruction),
+            Err(error) => format!(
+                "{:04}: DATA {} ({})",
+                address,
+                self.vm.memory().get(address),
+                error
+            ),
+        }
+    }
+
+    //Runs one instruction, and says something if it's noteworthy. Returns false if we should stop there.
+    fn single_step(&mut self, report: &mut String) -> bool {
+        let result = self.vm.step_recorded().map(|(stop, record)| {
+            if let Some(record) = record {
+                self.analysis.record(&record);
+            }
+            stop
+        });
+        match result {
+            Ok(None) => true,
+            Ok(Some(StopReason::Output(value))) => {
+                writeln!(report, "output: {}", value).unwrap();
+                true
+            }
+            Ok(Some(StopReason::Watchpoint(hits))) => {
+                for hit in hits {
+                    let what = match hit.access {
+                        Access::Read => format!("read {} from {}", hit.new, hit.address),
+                        _ => format!("wrote {} to {} (was {})", hit.new, hit.address, hit.old),
+                    };
+                    writeln!(
+                        report,
+                        "watchpoint: {:04}: {} {}",
+                        hit.ip, hit.instruction, what
+                    )
+                    .unwrap();
+                }
+                false
+            }
+            Ok(Some(StopReason::NeedsInput)) => {
+                writeln!(report, "waiting for input (use `input <values>`)").unwrap();
+                false
+            }
+            Ok(Some(StopReason::Halted)) => {
+                writeln!(report, "halted after {} steps", self.vm.steps()).unwrap();
+                false
+            }
+            Err(error) => {
+                writeln!(report, "error: {}", error).unwrap();
+                false
+            }
+        }
+    }
+
+    fn step(&mut self, args: &[&str]) -> Result<String, String> {
+        let count = match args.first() {
+            Some(count) => parse::<u64>(count)?,
+            None => 1,
+        };
+        let mut report = String::new();
+        for _ in 0..count {
+            if !self.single_step(&mut report) {
+                break;
+            }
+        }
+        report.push_str(&self.describe(self.vm.ip()));
+        Ok(report)
+    }
+
+    fn cont(&mut self) -> Result<String, String> {
+        let mut report = String::new();
+        //Always move at least once, otherwise we'd never leave a breakpoint
+        while self.single_step(&mut report) {
+            if self.breakpoints.contains(&self.vm.ip()) {
+                writeln!(report, "breakpoint at {}", self.vm.ip()).unwrap();
+                break;
+            }
+        }
+        report.push_str(&self.describe(self.vm.ip()));
+        Ok(report)
+    }
+
+    fn back(&mut self, args: &[&str]) -> Result<String, String> {
+        let count = match args.first() {
+            Some(count) => parse::<u64>(count)?,
+            None => 1,
+        };
+        let mut report = String::new();
+        for _ in 0..count {
+            if !self.vm.step_back() {
+                writeln!(report, "at the start of history").unwrap();
+                break;
+            }
+        }
+        report.push_str(&self.describe(self.vm.ip()));
+        Ok(report)
+    }
+
+    fn rewind(&mut self, args: &[&str]) -> Result<String, String> {
+        let address = parse::<usize>(args.first().ok_or("missing address")?)?;
+        let steps = self
+            .vm
+            .back_to_write(address)
+            .ok_or_else(|| format!("nothing has written to {}", address))?;
+        Ok(format!(
+            "went back {} step(s)\n{}",
+            steps,
+            self.describe(self.vm.ip())
+        ))
+    }
+
+    fn who(&self, args: &[&str]) -> Result<String, String> {
+        let address = parse::<usize>(args.first().ok_or("missing address")?)?;
+        let entry = self
+            .vm
+            .last_write_to(address)
+            .ok_or_else(|| format!("nothing has written to {}", address))?;
+        let write = entry.write.as_ref().unwrap();
+        Ok(format!(
+            "step {}, ip {}: {} -> {}",
+            entry.step, entry.ip, write.old, write.new
+        ))
+    }
+
+    fn set_breakpoint(&mut self, args: &[&str], set: bool) -> Result<String, String> {
+        let address = parse::<usize>(args.first().ok_or("missing address")?)?;
+        if set {
+            self.breakpoints.insert(address);
+            Ok(format!("breakpoint set at {}", address))
+        } else if self.breakpoints.remove(&address) {
+            Ok(format!("breakpoint at {} cleared", address))
+        } else {
+            Err(format!("no breakpoint at {}", address))
+        }
+    }
+
+    fn list_breakpoints(&self) -> String {
+        if self.breakpoints.is_empty() {
+            return "no breakpoints".to_string();
+        }
+        let lines: Vec<String> = self.breakpoints.iter().map(|&a| self.describe(a)).collect();
+        lines.join("\n")
+    }
+
+    fn watch(&mut self, args: &[&str]) -> Result<String, String> {
+        let address = match args.first() {
+            Some(address) => parse::<usize>(address)?,
+            None => {
+                let lines: Vec<String> = self
+                    .vm
+                    .watchpoints()
+                    .iter()
+                    .map(|w| format!("{:?} {}..{}", w.access, w.addresses.start, w.addresses.end))
+                    .collect();
+                return Ok(match lines.is_empty() {
+                    true => "no watchpoints".to_string(),
+                    false => lines.join("\n"),
+                });
+            }
+        };
+        let len = match args.get(1) {
+            Some(len) => parse::<usize>(len)?,
+            None => 1,
+        };
+        let access = match args.get(2).cloned().unwrap_or("write") {
+            "read" => Access::Read,
+            "write" => Access::Write,
+            "change" => Access::Change,
+            other => return Err(format!("`{}` isn't read, write or change", other)),
+        };
+        let addresses = range(address, len)?;
+        self.vm.watch(addresses.clone(), access);
+        Ok(format!(
+            "watching {}..{} ({:?})",
+            addresses.start, addresses.end, access
+        ))
+    }
+
+    fn unwatch(&mut self, args: &[&str]) -> Result<String, String> {
+        let address = parse::<usize>(args.first().ok_or("missing address")?)?;
+        match self.vm.unwatch(address) {
+            true => Ok(format!("stopped watching {}", address)),
+            false => Err(format!("nothing is watching {}", address)),
+        }
+    }
+
+    fn inst(&self, args: &[&str]) -> Result<String, String> {
+        let address = match args.first() {
+            Some(address) => parse::<usize>(address)?,
+            None => self.vm.ip(),
+        };
+        Ok(self.describe(address))
+    }
+
+    fn read(&self, args: &[&str]) -> Result<String, String> {
+        let address = parse::<usize>(args.first().ok_or("missing address")?)?;
+        let len = match args.get(1) {
+            Some(len) => parse::<usize>(len)?,
+            None => 1,
+        };
+        if len > MAX_READ {
+            return Err(format!("can't show more than {} cells at once", MAX_READ));
+        }
+        let lines: Vec<String> = range(address, len)?
+            .map(|address| {
+                format!(
+                    "{:04}: {} ({})",
+                    address,
+                    self.vm.memory().get(address),
+                    self.analysis.get(address)
+                )
+            })
+            .collect();
+        Ok(lines.join("\n"))
+    }
+
+    fn write(&mut self, args: &[&str]) -> Result<String, String> {
+        let address = parse::<usize>(args.first().ok_or("missing address")?)?;
+        let values = args[1..]
+            .iter()
+            .map(|value| parse::<W>(value))
+            .collect::<Result<Vec<W>, String>>()?;
+        if values.is_empty() {
+            return Err("missing value(s)".to_string());
+        }
+        //They're all shown back afterwards, so there can't be more than `read` shows
+        if values.len() > MAX_READ {
+            return Err(format!("can't write more than {} cells at once", MAX_READ));
+        }
+        for (address, value) in range(address, values.len())?.zip(values.iter()) {
+            self.vm.memory_mut().set(address, value.clone());
+        }
+        self.read(&[args[0], &values.len().to_string()])
+    }
+
+    fn input(&mut self, args: &[&str]) -> Result<String, String> {
+        let values = args
+            .iter()
+            .map(|value| parse::<W>(value))
+            .collect::<Result<Vec<W>, String>>()?;
+        let count = values.len();
+        for value in values {
+            self.vm.push_input(value);
+        }
+        Ok(format!("queued {} value(s)", count))
+    }
+
+    fn regs(&self) -> String {
+        format!(
+            "ip: {}, relative base: {}, steps: {}{}",
+            self.vm.ip(),
+            self.vm.relative_base(),
+            self.vm.steps(),
+            if self.vm.is_halted() { " (halted)" } else { "" }
+        )
+    }
+}
+
+//The len cells starting at an address, as long as they're all addressable
+fn range(address: usize, len: usize) -> Result<std::ops::Range<usize>, String> {
+    match address.checked_add(len) {
+        Some(end) => Ok(address..end),
+        None => Err("range out of bounds".to_string()),
+    }
+}
+
+fn parse<T: std::str::FromStr>(text: &str) -> Result<T, String> {
+    text.parse()
+        .map_err(|_| format!("`{}` isn't a valid number", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::debugger::Debugger;
+    use crate::vm::Vm;
+
+    fn debugger(program: Vec<i64>) -> Debugger {
+        Debugger::new(Vm::new(program))
+    }
+
+    #[test]
+    fn patch_and_continue() {
+        let mut debugger = debugger(vec![1, 0, 0, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+        assert_eq!(
+            debugger.command("set 1 9 10").unwrap(),
+            "0001: 9 (operand)\n0002: 10 (operand)"
+        );
+        assert_eq!(debugger.command("b 8").unwrap(), "breakpoint set at 8");
+        assert_eq!(debugger.command("c").unwrap(), "breakpoint at 8\n0008: HLT");
+        assert_eq!(
+            debugger.command("x 0").unwrap(),
+            "0000: 3500 (code, read, written)"
+        );
+        assert_eq!(
+            debugger.command("x 9 4").unwrap(),
+            "0009: 30 (read)\n0010: 40 (read)\n0011: 50 (read)\n0012: 0 (unknown)"
+        );
+        assert_eq!(
+            debugger.command("c").unwrap(),
+            "halted after 3 steps\n0008: HLT"
+        );
+        assert_eq!(debugger.command("q"), None);
+    }
+
+    #[test]
+    fn step_through_io() {
+        let mut debugger = debugger(vec![3, 0, 4, 0, 99]);
+        assert_eq!(debugger.command("inst").unwrap(), "0000: IN -> [0]");
+        assert_eq!(
+            debugger.command("step").unwrap(),
+            "waiting for input (use `input <values>`)\n0000: IN -> [0]"
+        );
+        debugger.command("input 42").unwrap();
+        assert_eq!(debugger.command("s 2").unwrap(), "output: 42\n0004: HLT");
+        assert_eq!(
+            debugger.command("regs").unwrap(),
+            "ip: 4, relative base: 0, steps: 2"
+        );
+        assert_eq!(
+            debugger.command("read x").unwrap(),
+            "error: `x` isn't a valid number"
+        );
+        for command in &[
+            "read 18446744073709551615 2",
+            "set 18446744073709551615 1 2",
+        ] {
+            assert_eq!(
+                debugger.command(command).unwrap(),
+                "error: range out of bounds"
+            );
+        }
+        assert_eq!(
+            debugger.command("read 0 100000000000").unwrap(),
+            "error: can't show more than 1000 cells at once"
+        );
+        let too_many = format!("set 0{}", " 1".repeat(1001));
+        assert_eq!(
+            debugger.command(&too_many).unwrap(),
+            "error: can't write more than 1000 cells at once"
+        );
+        assert_eq!(debugger.command("x 1000").unwrap(), "1000: 0 (unknown)");
+    }
+
+    #[test]
+    fn time_travel() {
+        //Patches its own second instruction into an output
+        let mut debugger = debugger(vec![1101, 100, 4, 4, 0, 4, 99]);
+        assert_eq!(
+            debugger.command("c").unwrap(),
+            "output: 4\nhalted after 3 steps\n0006: HLT"
+        );
+        assert_eq!(debugger.command("who 4").unwrap(), "step 0, ip 0: 0 -> 104");
+        assert_eq!(
+            debugger.command("rewind 4").unwrap(),
+            "went back 3 step(s)\n0000: ADD 100, 4 -> [4]"
+        );
+        assert_eq!(debugger.command("x 4").unwrap(), "0004: 0 (code, written)");
+        assert_eq!(debugger.command("s").unwrap(), "0004: OUT 4");
+        assert_eq!(
+            debugger.command("back 5").unwrap(),
+            "at the start of history\n0000: ADD 100, 4 -> [4]"
+        );
+        assert_eq!(
+            debugger.command("who 5").unwrap(),
+            "error: nothing has written to 5"
+        );
+    }
+
+    #[test]
+    fn watchpoints() {
+        //Which instruction feeds cell 0?
+        let mut debugger = debugger(vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+        assert_eq!(
+            debugger.command("watch 0").unwrap(),
+            "watching 0..1 (Write)"
+        );
+        assert_eq!(
+            debugger.command("c").unwrap(),
+            "watchpoint: 0004: MUL [3], [11] -> [0] wrote 3500 to 0 (was 1)\n0008: HLT"
+        );
+        assert_eq!(debugger.command("unwatch 0").unwrap(), "stopped watching 0");
+        debugger.command("back 2").unwrap();
+        debugger.command("watch 9 3 read").unwrap();
+        assert_eq!(debugger.command("watch").unwrap(), "Read 9..12");
+        assert_eq!(
+            debugger.command("s").unwrap(),
+            "watchpoint: 0000: ADD [9], [10] -> [3] read 30 from 9\n\
+             watchpoint: 0000: ADD [9], [10] -> [3] read 40 from 10\n\
+             0004: MUL [3], [11] -> [0]"
+        );
+        assert_eq!(
+            debugger.command("watch 1 1 sideways").unwrap(),
+            "error: `sideways` isn't read, write or change"
+        );
+        assert_eq!(
+            debugger.command("watch 18446744073709551615 2").unwrap(),
+            "error: range out of bounds"
+        );
+    }
+}
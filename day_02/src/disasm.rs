@@ -0,0 +1,131 @@
+use crate::analysis::Analysis;
+use crate::instruction::IntcodeInstruction;
+use crate::word::Word;
+
+//Walks through a program from the start, one instruction at a time, and lists what it finds:
+//
+//  0000: ADD [9], [10] -> [3]
+//  0004: HLT
+//  0005: DATA 30
+//
+//Cells that don't decode to an instruction are listed one by one as data, and we try again right after them.
+// This doesn't follow jumps, so constants stored right after some code might look like instructions!
+pub fn disassemble<W: Word>(program: &[W]) -> String {
+    let mut listing = String::new();
+    let mut address = 0;
+    while address < program.len() {
+        let line = match IntcodeInstruction::new(&program[address..]) {
+            Ok(instruction) => {
+                let line = format!("{:04}: {}\n", address, instruction);
+                address += instruction.size();
+                line
+            }
+            Err(_) => {
+                let line = format!("{:04}: DATA {}\n", address, program[address]);
+                address += 1;
+                line
+            }
+        };
+        listing.push_str(&line);
+    }
+    listing
+}
+
+//Like `disassemble`, but only cells the analysis found to be code are decoded, so constants don't get mistaken
+// for instructions. Everything else is listed as data, along with what it's used for:
+//
+//  0008: HLT
+//  0009: DATA 30 (read)
+pub fn annotate<W: Word>(program: &[W], analysis: &Analysis) -> String {
+    let mut listing = String::new();
+    let mut address = 0;
+    while address < program.len() {
+        let instruction = match analysis.get(address).code {
+            true => IntcodeInstruction::new(&program[address..]).ok(),
+            false => None,
+        };
+        let line = match instruction {
+            Some(instruction) => {
+                let line = format!("{:04}: {}\n", address, instruction);
+                address += instruction.size();
+                line
+            }
+            None => {
+                let usage = analysis.get(address);
+                let line = format!("{:04}: DATA {} ({})\n", address, program[address], usage);
+                address += 1;
+                line
+            }
+        };
+        listing.push_str(&line);
+    }
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::Analysis;
+    use crate::disasm::{annotate, disassemble};
+
+    #[test]
+    fn day_02_example() {
+        let program = [1i64, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50];
+        assert_eq!(
+            disassemble(&program),
+            "\
+0000: ADD [9], [10] -> [3]
+0004: MUL [3], [11] -> [0]
+0008: HLT
+0009: DATA 30
+0010: DATA 40
+0011: DATA 50
+"
+        );
+    }
+
+    #[test]
+    fn every_instruction() {
+        let program = [
+            21101i64, 1, -2, 3, 3, 7, 204, -1, 1005, 7, 0, 1106, 0, 0, 2107, 5, 6, 7, 1208, -4, 3,
+            2, 109, -5, 99, 1, 2,
+        ];
+        assert_eq!(
+            disassemble(&program),
+            "\
+0000: ADD 1, -2 -> [rb+3]
+0004: IN -> [7]
+0006: OUT [rb-1]
+0008: JT [7], 0
+0011: JF 0, 0
+0014: LT 5, [rb+6] -> [7]
+0018: EQ [rb-4], 3 -> [2]
+0022: ARB -5
+0024: HLT
+0025: DATA 1
+0026: DATA 2
+"
+        );
+    }
+
+    #[test]
+    fn annotated() {
+        //The trailing constants decode as an instruction, but nothing ever gets there
+        let program = [1i64, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50, 2, 14, 0, 0];
+        assert!(disassemble(&program).ends_with("0012: MUL [14], [0] -> [0]\n"));
+        assert_eq!(
+            annotate(&program, &Analysis::new(&program)),
+            "\
+0000: ADD [9], [10] -> [3]
+0004: MUL [3], [11] -> [0]
+0008: HLT
+0009: DATA 30 (read)
+0010: DATA 40 (read)
+0011: DATA 50 (read)
+0012: DATA 2 (unknown)
+0013: DATA 14 (unknown)
+0014: DATA 0 (unknown)
+0015: DATA 0 (unknown)
+"
+        );
+    }
+}
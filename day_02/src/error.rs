@@ -0,0 +1,73 @@
+use std::fmt;
+
+//Everything that can go wrong while decoding or executing an instruction
+#[derive(Clone, Debug, PartialEq)]
+pub enum VmErrorKind<W> {
+    //The opcode word isn't one we know about (the whole word, modes included)
+    UnknownOpcode(W),
+    //A mode digit that isn't 0, 1 or 2
+    InvalidMode(i64),
+    //Instructions never write to an immediate parameter
+    ImmediateDestination,
+    //The instruction needs more words than there are left in memory
+    Truncated,
+    //The IP is past everything in memory, where there's no instruction at all
+    RanOffEnd,
+    //Reading from or writing to an address that can't exist (negative, mostly)
+    ReadOutOfBounds(W),
+    WriteOutOfBounds(W),
+    //Jumping somewhere that can't exist
+    InvalidJump(W),
+    //The result doesn't fit in the word type (or the relative base doesn't fit in an i64)
+    Overflow,
+    //The program asked for input, and there was none left
+    InputExhausted,
+    //Ran out of the step budget (the limit is included)
+    StepLimitExceeded(u64),
+    //Came back to the same place with nothing having changed in between, so we'd go around forever.
+    // The period is how many steps one trip around the loop takes.
+    InfiniteLoop { period: u64 },
+}
+
+//An error, along with where it happened: the instruction pointer and the raw words of the instruction
+#[derive(Clone, Debug, PartialEq)]
+pub struct VmError<W = i64> {
+    pub kind: VmErrorKind<W>,
+    pub ip: usize,
+    pub words: Vec<W>,
+}
+
+impl<W: fmt::Display> fmt::Display for VmErrorKind<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VmErrorKind::UnknownOpcode(word) => write!(f, "unknown opcode {}", word),
+            VmErrorKind::InvalidMode(mode) => write!(f, "invalid parameter mode {}", mode),
+            VmErrorKind::ImmediateDestination => write!(f, "destination is in immediate mode"),
+            VmErrorKind::Truncated => write!(f, "instruction runs past the end of memory"),
+            VmErrorKind::RanOffEnd => write!(f, "ran off the end of the program"),
+            VmErrorKind::ReadOutOfBounds(address) => write!(f, "read out of bounds at {}", address),
+            VmErrorKind::WriteOutOfBounds(address) => {
+                write!(f, "write out of bounds at {}", address)
+            }
+            VmErrorKind::InvalidJump(target) => write!(f, "invalid jump target {}", target),
+            VmErrorKind::Overflow => write!(f, "arithmetic overflow"),
+            VmErrorKind::InputExhausted => write!(f, "ran out of input"),
+            VmErrorKind::StepLimitExceeded(limit) => write!(f, "step limit of {} exceeded", limit),
+            VmErrorKind::InfiniteLoop { period } => {
+                write!(f, "stuck in an infinite loop of {} steps", period)
+            }
+        }
+    }
+}
+
+impl<W: fmt::Display> fmt::Display for VmError<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let words: Vec<String> = self.words.iter().map(|w| w.to_string()).collect();
+        match words.is_empty() {
+            true => write!(f, "{} (ip {})", self.kind, self.ip),
+            false => write!(f, "{} (ip {}: {})", self.kind, self.ip, words.join(",")),
+        }
+    }
+}
+
+impl<W: fmt::Debug + fmt::Display> std::error::Error for VmError<W> {}
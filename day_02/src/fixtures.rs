@@ -0,0 +1,53 @@
+//Programs and checks that tests in more than one module need
+use crate::compile::Compiled;
+use crate::network::Network;
+use crate::vm::{RunState, StopReason, Vm};
+
+//Outputs 999 if the input is below 8, 1000 if it's equal to 8, and 1001 if it's greater
+pub const AROUND_8: [i64; 47] = [
+    3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0, 0,
+    1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4, 20, 1105,
+    1, 46, 98, 99,
+];
+
+//Goes around twice, turning its first instruction from an ADD into a MUL along the way, then outputs 15
+pub const REWRITES_ITSELF: [i64; 24] = [
+    1001, 22, 3, 22, 1101, 2, 1000, 0, 1001, 23, -1, 23, 1005, 23, 0, 4, 22, 99, 0, 0, 0, 0, 2, 2,
+];
+
+//Runs a machine to the end both compiled and interpreted, and makes sure we end up in the same place
+pub fn same_compiled(vm: Vm, input: &[i64]) {
+    let program = Compiled::new(vm.memory().clone());
+    let (mut compiled, mut naive) = (vm.clone(), vm);
+    for &value in input {
+        compiled.push_input(value);
+        naive.push_input(value);
+    }
+    loop {
+        let state = program.run(&mut compiled);
+        assert_eq!(state, naive.run());
+        assert_eq!(compiled.memory(), naive.memory());
+        if !matches!(
+            state,
+            Ok(RunState {
+                reason: StopReason::Output(_),
+                ..
+            })
+        ) {
+            break;
+        }
+    }
+}
+
+//Runs a network both threaded and not, and makes sure we end up in the same place
+pub fn same_threaded(network: Network) -> Network {
+    let mut threaded = network.clone();
+    let mut single = network;
+    assert_eq!(threaded.run_threaded(), single.run());
+    assert_eq!(threaded.output(), single.output());
+    for (threaded, single) in threaded.machines().iter().zip(single.machines()) {
+        assert_eq!(threaded.memory(), single.memory());
+        assert_eq!(threaded.steps(), single.steps());
+    }
+    threaded
+}
@@ -0,0 +1,290 @@
+use crate::error::VmErrorKind;
+use crate::word::Word;
+use std::fmt;
+
+//Parameters either point somewhere in memory, or are the value itself
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParamMode {
+    Position,
+    Immediate,
+    Relative,
+}
+impl ParamMode {
+    //Modes are stored as single digits to the left of the opcode
+    pub fn new(digit: i64) -> Option<Self> {
+        match digit {
+            0 => Some(ParamMode::Position),
+            1 => Some(ParamMode::Immediate),
+            2 => Some(ParamMode::Relative),
+            _ => None,
+        }
+    }
+}
+
+//A raw operand, along with how it should be interpreted
+#[derive(Clone, Debug, PartialEq)]
+pub struct Param<W> {
+    pub mode: ParamMode,
+    pub value: W,
+}
+
+//Defines what Intcode instructions look like~
+#[derive(Clone, Debug, PartialEq)]
+pub enum IntcodeInstruction<W> {
+    Add {
+        lhs: Param<W>,
+        rhs: Param<W>,
+        dest: Param<W>,
+    },
+    Mul {
+        lhs: Param<W>,
+        rhs: Param<W>,
+        dest: Param<W>,
+    },
+    Input {
+        dest: Param<W>,
+    },
+    Output {
+        src: Param<W>,
+    },
+    JumpIfTrue {
+        cond: Param<W>,
+        target: Param<W>,
+    },
+    JumpIfFalse {
+        cond: Param<W>,
+        target: Param<W>,
+    },
+    LessThan {
+        lhs: Param<W>,
+        rhs: Param<W>,
+        dest: Param<W>,
+    },
+    Equals {
+        lhs: Param<W>,
+        rhs: Param<W>,
+        dest: Param<W>,
+    },
+    AdjustBase {
+        offset: Param<W>,
+    },
+    Halt,
+}
+impl<W: Word> IntcodeInstruction<W> {
+    //Instructions are built from a slice (of which 1 element is read, and perhaps 3 more)
+    // Moving through the program is the executor's job now, since jumps can send us anywhere!
+    pub fn new(params: &[W]) -> Result<Self, VmErrorKind<W>> {
+        let first = params.first().ok_or(VmErrorKind::RanOffEnd)?;
+        let unknown = || VmErrorKind::UnknownOpcode(first.clone());
+        //The first word is the opcode (last two digits) and one mode digit per operand, right to left
+        let word = first
+            .to_i64()
+            .filter(|&word| word >= 0)
+            .ok_or_else(unknown)?;
+        let size = match word % 100 {
+            1 | 2 | 7 | 8 => 4,
+            5 | 6 => 3,
+            3 | 4 | 9 => 2,
+            99 => 1,
+            _ => return Err(unknown()),
+        };
+        //Mode digits for operands that don't exist don't mean anything, so they'd better not be there
+        if word / 10i64.pow(size as u32 + 1) != 0 {
+            return Err(unknown());
+        }
+        if params.len() < size {
+            return Err(VmErrorKind::Truncated);
+        }
+        let param = |n: usize| {
+            let digit = word / 10i64.pow(n as u32 + 1) % 10;
+            Ok(Param {
+                mode: ParamMode::new(digit).ok_or(VmErrorKind::InvalidMode(digit))?,
+                value: params[n].clone(),
+            })
+        };
+        //Destinations are addresses, so they can't be immediate
+        let dest = |n: usize| match param(n)? {
+            Param {
+                mode: ParamMode::Immediate,
+                ..
+            } => Err(VmErrorKind::ImmediateDestination),
+            param => Ok(param),
+        };
+        Ok(match word % 100 {
+            1 => IntcodeInstruction::Add {
+                lhs: param(1)?,
+                rhs: param(2)?,
+                dest: dest(3)?,
+            },
+            2 => IntcodeInstruction::Mul {
+                lhs: param(1)?,
+                rhs: param(2)?,
+                dest: dest(3)?,
+            },
+            3 => IntcodeInstruction::Input { dest: dest(1)? },
+            4 => IntcodeInstruction::Output { src: param(1)? },
+            5 => IntcodeInstruction::JumpIfTrue {
+                cond: param(1)?,
+                target: param(2)?,
+            },
+            6 => IntcodeInstruction::JumpIfFalse {
+                cond: param(1)?,
+                target: param(2)?,
+            },
+            7 => IntcodeInstruction::LessThan {
+                lhs: param(1)?,
+                rhs: param(2)?,
+                dest: dest(3)?,
+            },
+            8 => IntcodeInstruction::Equals {
+                lhs: param(1)?,
+                rhs: param(2)?,
+                dest: dest(3)?,
+            },
+            9 => IntcodeInstruction::AdjustBase { offset: param(1)? },
+            99 => IntcodeInstruction::Halt,
+            _ => unreachable!("unknown opcodes were ruled out above"),
+        })
+    }
+
+    //How many words the instruction takes up, opcode included.
+    // That's how far the executor moves forward, unless we jumped somewhere.
+    pub fn size(&self) -> usize {
+        match self {
+            IntcodeInstruction::Add { .. }
+            | IntcodeInstruction::Mul { .. }
+            | IntcodeInstruction::LessThan { .. }
+            | IntcodeInstruction::Equals { .. } => 4,
+            IntcodeInstruction::JumpIfTrue { .. } | IntcodeInstruction::JumpIfFalse { .. } => 3,
+            IntcodeInstruction::Input { .. }
+            | IntcodeInstruction::Output { .. }
+            | IntcodeInstruction::AdjustBase { .. } => 2,
+            IntcodeInstruction::Halt => 1,
+        }
+    }
+
+    //The opcode, without the mode digits
+    pub fn opcode(&self) -> i64 {
+        match self {
+            IntcodeInstruction::Add { .. } => 1,
+            IntcodeInstruction::Mul { .. } => 2,
+            IntcodeInstruction::Input { .. } => 3,
+            IntcodeInstruction::Output { .. } => 4,
+            IntcodeInstruction::JumpIfTrue { .. } => 5,
+            IntcodeInstruction::JumpIfFalse { .. } => 6,
+            IntcodeInstruction::LessThan { .. } => 7,
+            IntcodeInstruction::Equals { .. } => 8,
+            IntcodeInstruction::AdjustBase { .. } => 9,
+            IntcodeInstruction::Halt => 99,
+        }
+    }
+
+    //Short names for listings, which is also how the assembler spells them
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            IntcodeInstruction::Add { .. } => "ADD",
+            IntcodeInstruction::Mul { .. } => "MUL",
+            IntcodeInstruction::Input { .. } => "IN",
+            IntcodeInstruction::Output { .. } => "OUT",
+            IntcodeInstruction::JumpIfTrue { .. } => "JT",
+            IntcodeInstruction::JumpIfFalse { .. } => "JF",
+            IntcodeInstruction::LessThan { .. } => "LT",
+            IntcodeInstruction::Equals { .. } => "EQ",
+            IntcodeInstruction::AdjustBase { .. } => "ARB",
+            IntcodeInstruction::Halt => "HLT",
+        }
+    }
+}
+
+//Position parameters are [address], immediate ones are just the value, and relative ones are [rb+offset]
+impl<W: Word> fmt::Display for Param<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mode {
+            ParamMode::Position => write!(f, "[{}]", self.value),
+            ParamMode::Immediate => write!(f, "{}", self.value),
+            ParamMode::Relative if self.value < W::default() => write!(f, "[rb{}]", self.value),
+            ParamMode::Relative => write!(f, "[rb+{}]", self.value),
+        }
+    }
+}
+
+//Something like `ADD [0], 3 -> [rb+1]`: sources first, then the destination after an arrow
+impl<W: Word> fmt::Display for IntcodeInstruction<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.mnemonic())?;
+        match self {
+            IntcodeInstruction::Add { lhs, rhs, dest }
+            | IntcodeInstruction::Mul { lhs, rhs, dest }
+            | IntcodeInstruction::LessThan { lhs, rhs, dest }
+            | IntcodeInstruction::Equals { lhs, rhs, dest } => {
+                write!(f, " {}, {} -> {}", lhs, rhs, dest)
+            }
+            IntcodeInstruction::JumpIfTrue { cond, target }
+            | IntcodeInstruction::JumpIfFalse { cond, target } => {
+                write!(f, " {}, {}", cond, target)
+            }
+            IntcodeInstruction::Input { dest } => write!(f, " -> {}", dest),
+            IntcodeInstruction::Output { src: param }
+            | IntcodeInstruction::AdjustBase { offset: param } => {
+                write!(f, " {}", param)
+            }
+            IntcodeInstruction::Halt => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instruction::*;
+
+    #[test]
+    fn param_modes() {
+        //1002 is a Mul with the second operand in immediate mode
+        let instruction = IntcodeInstruction::new(&[1002i64, 4, 3, 4]).unwrap();
+        assert_eq!(
+            instruction,
+            IntcodeInstruction::Mul {
+                lhs: Param {
+                    mode: ParamMode::Position,
+                    value: 4
+                },
+                rhs: Param {
+                    mode: ParamMode::Immediate,
+                    value: 3
+                },
+                dest: Param {
+                    mode: ParamMode::Position,
+                    value: 4
+                },
+            }
+        );
+        assert_eq!(instruction.size(), 4);
+    }
+
+    #[test]
+    fn io_instructions() {
+        let instruction = IntcodeInstruction::new(&[104i64, 7]).unwrap();
+        assert_eq!(
+            instruction,
+            IntcodeInstruction::Output {
+                src: Param {
+                    mode: ParamMode::Immediate,
+                    value: 7
+                }
+            }
+        );
+        assert_eq!(instruction.size(), 2);
+    }
+
+    #[test]
+    fn decoding_errors() {
+        let decode = |params: &[i64]| IntcodeInstruction::new(params).unwrap_err();
+        assert_eq!(decode(&[42, 0, 0, 0]), VmErrorKind::UnknownOpcode(42));
+        assert_eq!(decode(&[-1]), VmErrorKind::UnknownOpcode(-1));
+        assert_eq!(decode(&[10099]), VmErrorKind::UnknownOpcode(10099));
+        assert_eq!(decode(&[301, 0, 0, 0]), VmErrorKind::InvalidMode(3));
+        assert_eq!(decode(&[11101, 0, 0, 0]), VmErrorKind::ImmediateDestination);
+        assert_eq!(decode(&[1, 0, 0]), VmErrorKind::Truncated);
+        assert_eq!(decode(&[]), VmErrorKind::RanOffEnd);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::word::Word;
+use std::collections::VecDeque;
+use std::io::{BufRead, Write};
+use std::sync::mpsc::{Receiver, Sender};
+
+//Where the Input instruction gets its values from.
+// Returning None means there's nothing left to read.
+pub trait Input<W = i64> {
+    fn read(&mut self) -> Option<W>;
+}
+
+//Where the Output instruction sends its values to.
+pub trait Output<W = i64> {
+    fn write(&mut self, value: W);
+}
+
+//Scripted input: values are consumed front to back, and outputs are collected in order
+impl<W> Input<W> for VecDeque<W> {
+    fn read(&mut self) -> Option<W> {
+        self.pop_front()
+    }
+}
+impl<W> Output<W> for VecDeque<W> {
+    fn write(&mut self, value: W) {
+        self.push_back(value)
+    }
+}
+
+//`vec![1, 2, 3].into_iter()` works as input too
+impl<W> Input<W> for std::vec::IntoIter<W> {
+    fn read(&mut self) -> Option<W> {
+        self.next()
+    }
+}
+impl<W> Output<W> for Vec<W> {
+    fn write(&mut self, value: W) {
+        self.push(value)
+    }
+}
+
+//Closures, for when the value depends on what happened before
+impl<W, F: FnMut() -> Option<W>> Input<W> for F {
+    fn read(&mut self) -> Option<W> {
+        self()
+    }
+}
+impl<W, F: FnMut(W)> Output<W> for F {
+    fn write(&mut self, value: W) {
+        self(value)
+    }
+}
+
+//Channels, so a VM can talk to another thread. A hung up sender means there's no more input.
+impl<W> Input<W> for Receiver<W> {
+    fn read(&mut self) -> Option<W> {
+        self.recv().ok()
+    }
+}
+impl<W> Output<W> for Sender<W> {
+    fn write(&mut self, value: W) {
+        //If nobody's listening anymore, the value is simply lost
+        let _ = self.send(value);
+    }
+}
+
+//Interactive input: prompt on stderr, then read one number per line.
+// Lines that aren't numbers get asked for again, and end of file means no more input.
+pub struct Interactive<R: BufRead> {
+    reader: R,
+}
+impl Interactive<std::io::StdinLock<'static>> {
+    pub fn stdin() -> Self {
+        Interactive::new(std::io::stdin().lock())
+    }
+}
+impl<R: BufRead> Interactive<R> {
+    pub fn new(reader: R) -> Self {
+        Interactive { reader }
+    }
+}
+impl<W: Word, R: BufRead> Input<W> for Interactive<R> {
+    fn read(&mut self) -> Option<W> {
+        loop {
+            eprint!("> ");
+            let _ = std::io::stderr().flush();
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) | Err(_) => return None,
+                Ok(_) => match line.trim().parse() {
+                    Ok(value) => return Some(value),
+                    Err(_) => eprintln!("Please enter a number!"),
+                },
+            }
+        }
+    }
+}
+
+//Prints every output on its own line
+pub struct Stdout;
+impl<W: Word> Output<W> for Stdout {
+    fn write(&mut self, value: W) {
+        println!("{}", value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::*;
+
+    #[test]
+    fn interactive_skips_garbage() {
+        let mut input = Interactive::new("hello\n-42\n".as_bytes());
+        assert_eq!(input.read(), Some(-42i64));
+        assert_eq!(Input::<i64>::read(&mut input), None);
+    }
+
+    #[test]
+    fn closures() {
+        let mut n = 0;
+        let mut counter = || {
+            n += 1;
+            Some(n as i64)
+        };
+        assert_eq!(Input::read(&mut counter), Some(1));
+        assert_eq!(Input::read(&mut counter), Some(2));
+    }
+}
@@ -0,0 +1,41 @@
+//The Intcode computer lives here, so that both the puzzle binary and tests can drive it
+pub mod analysis;
+pub mod asm;
+pub mod bigint;
+pub mod cfg;
+pub mod compile;
+pub mod debugger;
+pub mod disasm;
+pub mod error;
+#[cfg(test)]
+mod fixtures;
+pub mod instruction;
+pub mod io;
+pub mod memory;
+pub mod network;
+pub mod parse;
+pub mod search;
+pub mod snapshot;
+pub mod symbolic;
+pub mod threaded;
+pub mod trace;
+pub mod vm;
+pub mod word;
+
+pub use analysis::{Analysis, CellKind, Usage};
+pub use bigint::BigInt;
+pub use compile::Compiled;
+pub use error::{VmError, VmErrorKind};
+pub use instruction::{IntcodeInstruction, Param, ParamMode};
+pub use io::{Input, Output};
+pub use memory::Memory;
+pub use network::{Network, NetworkError, NetworkOutcome, Topology};
+pub use parse::{load_program, parse_program};
+pub use search::{Crash, Found, Outcome, Search, SearchError, Strategy};
+pub use snapshot::SnapshotError;
+pub use symbolic::{Expr, Polynomial, Symbolic, SymbolicError};
+pub use vm::{
+    execute, execute_with_io, Access, MemoryWrite, RunState, StepRecord, StopReason, UndoEntry, Vm,
+    WatchHit, Watchpoint,
+};
+pub use word::Word;
@@ -1,174 +1,74 @@
-//Defines what Intcode instructions look like~
-enum IntcodeInstruction {
-    Add { lhs: u32, rhs: u32, dest: u32 },
-    Mul { lhs: u32, rhs: u32, dest: u32 },
-    Halt,
-}
-impl IntcodeInstruction {
-    //Instructions are built from a slice (of which 1 element is read, and perhaps 3 more)
-    // We also keep track of the index in the program, and hope we'll only need to move forward through those instructions ^u^'
-    fn new(params: &[u32], index: &mut usize) -> Self {
-        match params[0] {
-            1 => {
-                *index += 4;
-                IntcodeInstruction::Add {
-                    lhs: params[1],
-                    rhs: params[2],
-                    dest: params[3],
-                }
-            }
-            2 => {
-                *index += 4;
-                IntcodeInstruction::Mul {
-                    lhs: params[1],
-                    rhs: params[2],
-                    dest: params[3],
-                }
-            }
-            99 => IntcodeInstruction::Halt,
-            _ => {
-                panic!("Attempting to create an invalid instruction type!");
-            }
-        }
-    }
-}
-
-//Executing an instruction means modifying the intcode program, so keep a mutable reference to it!
-fn execute_at(index: &mut usize, intcode: &mut [u32]) {
-    let instruction = IntcodeInstruction::new(&intcode[*index..], index);
-    match instruction {
-        IntcodeInstruction::Add { lhs, rhs, dest } => {
-            intcode[dest as usize] = intcode[lhs as usize] + intcode[rhs as usize];
-        }
-        IntcodeInstruction::Mul { lhs, rhs, dest } => {
-            intcode[dest as usize] = intcode[lhs as usize] * intcode[rhs as usize];
-        }
-        IntcodeInstruction::Halt => {
-            //That's one way of making sure the program halts ¯\_(ツ)_/¯ (with the appropriate condition in execute)
-            *index = intcode.len()
-        }
-    }
-}
-
-//Repeatedly execute the instruction at the current index.
-fn execute(mut intcode: &mut [u32]) {
-    let mut index: usize = 0;
-
-    //Don't run further than expected, and stop on Halt
-    while index < intcode.len() {
-        //Index will change as soon as we create a new IntcodeInstruction.
-        execute_at(&mut index, &mut intcode)
-    }
-}
+use day_02::{execute, load_program, parse_program, Search, Strategy, Symbolic};
 
 fn main() {
-    // PART 1
     // INPUT
-    let mut intcode = [
-        1, 0, 0, 3, 1, 1, 2, 3, 1, 3, 4, 3, 1, 5, 0, 3, 2, 1, 10, 19, 1, 6, 19, 23, 2, 23, 6, 27,
-        2, 6, 27, 31, 2, 13, 31, 35, 1, 10, 35, 39, 2, 39, 13, 43, 1, 43, 13, 47, 1, 6, 47, 51, 1,
-        10, 51, 55, 2, 55, 6, 59, 1, 5, 59, 63, 2, 9, 63, 67, 1, 6, 67, 71, 2, 9, 71, 75, 1, 6, 75,
-        79, 2, 79, 13, 83, 1, 83, 10, 87, 1, 13, 87, 91, 1, 91, 10, 95, 2, 9, 95, 99, 1, 5, 99,
-        103, 2, 10, 103, 107, 1, 107, 2, 111, 1, 111, 5, 0, 99, 2, 14, 0, 0,
-    ];
+    //Our own puzzle input is built in, but another one can be given as a path (or `-` for stdin)
+    let program: Vec<i64> = match std::env::args().nth(1) {
+        Some(path) => load_program(&path).unwrap_or_else(|error| fail(error)),
+        None => parse_program(include_str!("input.txt")).expect("The built-in input is broken"),
+    };
+
+    // PART 1
+    let mut intcode = program.clone();
+    if intcode.len() < 3 {
+        fail("the program is too short to have a noun and verb");
+    }
     //Initialize the thing
     intcode[1] = 12;
     intcode[2] = 2;
     //Execute the thing
-    execute(&mut intcode);
+    execute(&mut intcode).unwrap_or_else(|error| fail(error));
     //Now read the thing >:3
     println!("{}", intcode[0]);
 
     //PART 2
-    //Is there any point in NOT trying every combination here?
-    'outer: for noun in 0..100 {
-        for verb in 0..100 {
-            let mut intcode = [
-                1, 0, 0, 3, 1, 1, 2, 3, 1, 3, 4, 3, 1, 5, 0, 3, 2, 1, 10, 19, 1, 6, 19, 23, 2, 23,
-                6, 27, 2, 6, 27, 31, 2, 13, 31, 35, 1, 10, 35, 39, 2, 39, 13, 43, 1, 43, 13, 47, 1,
-                6, 47, 51, 1, 10, 51, 55, 2, 55, 6, 59, 1, 5, 59, 63, 2, 9, 63, 67, 1, 6, 67, 71,
-                2, 9, 71, 75, 1, 6, 75, 79, 2, 79, 13, 83, 1, 83, 10, 87, 1, 13, 87, 91, 1, 91, 10,
-                95, 2, 9, 95, 99, 1, 5, 99, 103, 2, 10, 103, 107, 1, 107, 2, 111, 1, 111, 5, 0, 99,
-                2, 14, 0, 0,
-            ];
-            intcode[1] = noun;
-            intcode[2] = verb;
-            execute(&mut intcode);
-            if intcode[0] == 19690720 {
-                println!(
-                    "verb: {}, noun:{}, result:{}",
-                    verb,
-                    noun,
-                    100 * noun + verb
-                );
-                //Let's not be entirely horrible and stop when we reach the solution, ok
-                break 'outer;
-            }
-        }
-    }
-}
-
-// TESTS
-#[cfg(test)]
-mod tests {
-    #[test]
-    fn part1_ex1() {
-        let mut intcode = [1, 0, 0, 0, 99];
-        crate::execute(&mut intcode);
-        //Oof.
-        // - Get an iterator over that array,
-        // - map() each u32 to a String,
-        // - collect the values into a Vec (it cannot be a slice, since those are borrows!)
-        // - finally, join the pieces together.
-        assert_eq!(
-            intcode
-                .into_iter()
-                .map(|i: &u32| i.to_string())
-                .collect::<Vec<String>>()
-                .join(","),
-            "2,0,0,0,99"
-        );
-    }
-
-    #[test]
-    fn part1_ex2() {
-        let mut intcode = [2, 3, 0, 3, 99];
-        crate::execute(&mut intcode);
-        assert_eq!(
-            intcode
-                .into_iter()
-                .map(|i: &u32| i.to_string())
-                .collect::<Vec<String>>()
-                .join(","),
-            "2,3,0,6,99"
+    let search = Search::new(program.clone())
+        .with_cell(1, 0..100)
+        .with_cell(2, 0..100)
+        .with_step_limit(100_000);
+    //The program only ever adds and multiplies, so cell 0 is some formula of the noun and verb: let's find out which
+    let mut symbolic = Symbolic::new(&program)
+        .with_unknown(1, "noun")
+        .with_unknown(2, "verb")
+        .with_step_limit(100_000);
+    let solved = symbolic.run().ok().and_then(|()| {
+        let result = symbolic.get(0);
+        println!("cell 0 = {}", result);
+        //The formula doesn't know about overflow, so a solution only counts once the real thing agrees
+        result
+            .polynomial()?
+            .solve(TARGET, &[("noun", 0..100), ("verb", 0..100)])?
+            .into_iter()
+            .find(|values| {
+                matches!(search.outcome(values), Ok(outcome) if outcome.vm.memory().get(0) == TARGET)
+            })
+    });
+    //When there's no formula to solve, there's always trying every combination (on every core we've got)
+    let solution = solved.or_else(|| {
+        search
+            .run(Strategy::Parallel { threads: 0 }, |outcome| {
+                outcome.vm.memory().get(0).cmp(&TARGET)
+            })
+            .unwrap_or_else(|error| fail(error))
+            .matches
+            .into_iter()
+            .next()
+    });
+    if let Some(solution) = solution {
+        let (noun, verb) = (solution[0], solution[1]);
+        println!(
+            "verb: {}, noun:{}, result:{}",
+            verb,
+            noun,
+            100 * noun + verb
         );
     }
+}
 
-    #[test]
-    fn part1_ex3() {
-        let mut intcode = [2, 4, 4, 5, 99, 0];
-        crate::execute(&mut intcode);
-        assert_eq!(
-            intcode
-                .into_iter()
-                .map(|i: &u32| i.to_string())
-                .collect::<Vec<String>>()
-                .join(","),
-            "2,4,4,5,99,9801"
-        );
-    }
+const TARGET: i64 = 19690720;
 
-    #[test]
-    fn part1_ex4() {
-        let mut intcode = [1, 1, 1, 4, 99, 5, 6, 0, 99];
-        crate::execute(&mut intcode);
-        assert_eq!(
-            intcode
-                .into_iter()
-                .map(|i: &u32| i.to_string())
-                .collect::<Vec<String>>()
-                .join(","),
-            "30,1,1,4,2,5,6,0,99"
-        );
-    }
+//For programs that don't load or don't run: there's no answer to give
+fn fail(error: impl std::fmt::Display) -> ! {
+    eprintln!("error: {}", error);
+    std::process::exit(1);
 }
@@ -0,0 +1,132 @@
+use crate::word::Word;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+//Addresses below this are stored in a plain Vec, which grows as needed.
+// Anything above goes in a map, so poking at address 1_000_000_000 doesn't allocate gigabytes.
+pub(crate) const DENSE_LIMIT: usize = 1 << 20;
+
+//Intcode memory: as big as it needs to be, and every cell we never wrote to reads as 0
+#[derive(Clone, Debug, PartialEq)]
+pub struct Memory<W = i64> {
+    dense: Vec<W>,
+    sparse: BTreeMap<usize, W>,
+}
+
+//Deriving Default would require W: Default, which isn't needed for an empty memory
+impl<W> Default for Memory<W> {
+    fn default() -> Self {
+        Memory {
+            dense: Vec::new(),
+            sparse: BTreeMap::new(),
+        }
+    }
+}
+
+impl<W: Word> Memory<W> {
+    pub fn new() -> Self {
+        Memory::default()
+    }
+
+    pub fn get(&self, address: usize) -> W {
+        let cell = if address < DENSE_LIMIT {
+            self.dense.get(address)
+        } else {
+            self.sparse.get(&address)
+        };
+        cell.cloned().unwrap_or_default()
+    }
+
+    pub fn set(&mut self, address: usize, value: W) {
+        if address < DENSE_LIMIT {
+            if address >= self.dense.len() {
+                self.dense.resize(address + 1, W::default());
+            }
+            self.dense[address] = value;
+        } else {
+            self.sparse.insert(address, value);
+        }
+    }
+
+    //The few words an instruction can be made of, starting at `address`.
+    // This stops at the end of memory, so that the decoder can tell when an instruction is cut short.
+    pub fn fetch(&self, address: usize) -> Cow<'_, [W]> {
+        let end = self.len().min(address.saturating_add(4));
+        if end <= self.dense.len() {
+            Cow::Borrowed(&self.dense[address.min(end)..end])
+        } else {
+            Cow::Owned((address..end).map(|address| self.get(address)).collect())
+        }
+    }
+
+    //One past the highest address that has been written to (or loaded)
+    pub fn len(&self) -> usize {
+        match self.sparse.keys().next_back() {
+            Some(&address) => address + 1,
+            None => self.dense.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    //Every cell that's actually stored (including zeroes in the dense part), in address order
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &W)> {
+        self.dense
+            .iter()
+            .enumerate()
+            .chain(self.sparse.iter().map(|(&address, value)| (address, value)))
+    }
+}
+
+impl<W: Word> From<&[W]> for Memory<W> {
+    fn from(program: &[W]) -> Self {
+        Memory::from(program.to_vec())
+    }
+}
+
+impl<W: Word> From<Vec<W>> for Memory<W> {
+    fn from(mut program: Vec<W>) -> Self {
+        let sparse = if program.len() > DENSE_LIMIT {
+            program
+                .drain(DENSE_LIMIT..)
+                .enumerate()
+                .map(|(offset, value)| (DENSE_LIMIT + offset, value))
+                .collect()
+        } else {
+            BTreeMap::new()
+        };
+        Memory {
+            dense: program,
+            sparse,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::memory::*;
+
+    #[test]
+    fn reads_default_to_zero() {
+        let mut memory = Memory::from(vec![1i64, 2, 3]);
+        assert_eq!(memory.get(2), 3);
+        assert_eq!(memory.get(3), 0);
+        memory.set(10, 7);
+        assert_eq!(memory.get(10), 7);
+        assert_eq!(memory.get(9), 0);
+        assert_eq!(memory.len(), 11);
+        assert_eq!(memory.fetch(0).len(), 4);
+        assert_eq!(memory.fetch(9)[..], [0, 7]);
+    }
+
+    #[test]
+    fn far_away_writes_are_sparse() {
+        let mut memory: Memory = Memory::new();
+        memory.set(1_000_000_000, 42);
+        assert_eq!(memory.get(1_000_000_000), 42);
+        assert_eq!(memory.len(), 1_000_000_001);
+        assert!(memory.dense.is_empty());
+    }
+}
@@ -0,0 +1,229 @@
+use crate::error::VmError;
+use crate::vm::{StopReason, Vm};
+use crate::word::Word;
+use std::convert::TryFrom;
+use std::fmt;
+
+//How the machines' outputs are wired to each other's inputs
+#[derive(Clone, Debug, PartialEq)]
+pub enum Topology {
+    //Each machine feeds the next one, and the last one's outputs leave the network
+    Chain,
+    //Like a chain, except the last machine feeds the first one too (its outputs still show up in the network's)
+    Ring,
+    //Machines send packets: a destination address (the machine's index), then `payload` words.
+    // Packets to addresses that don't exist leave the network, address included.
+    Bus { payload: usize },
+}
+
+//How running the whole network ended
+#[derive(Clone, Debug, PartialEq)]
+pub enum NetworkOutcome {
+    //Every machine halted
+    Halted,
+    //Every machine that's still running is waiting for input, and nobody's left to send it any.
+    // Push some input and run again to get them going.
+    Deadlock { waiting: Vec<usize> },
+}
+
+//A machine crashed, taking the network down with it
+#[derive(Clone, Debug, PartialEq)]
+pub struct NetworkError<W> {
+    pub machine: usize,
+    pub error: VmError<W>,
+}
+
+impl<W: fmt::Display> fmt::Display for NetworkError<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "machine {}: {}", self.machine, self.error)
+    }
+}
+
+impl<W: fmt::Debug + fmt::Display> std::error::Error for NetworkError<W> {}
+
+//Where a value output by machine `from` should go: to some machine, or out of the network (None).
+// With a bus, words pile up in `packet` until it's complete.
+pub(crate) fn route<W: Word>(
+    topology: &Topology,
+    count: usize,
+    from: usize,
+    packet: &mut Vec<W>,
+    value: W,
+) -> Vec<(Option<usize>, W)> {
+    match *topology {
+        Topology::Chain if from == count - 1 => vec![(None, value)],
+        Topology::Ring if from == count - 1 => vec![(None, value.clone()), (Some(0), value)],
+        Topology::Chain | Topology::Ring => vec![(Some(from + 1), value)],
+        Topology::Bus { payload } => {
+            packet.push(value);
+            if packet.len() < payload + 1 {
+                return Vec::new();
+            }
+            let packet = std::mem::take(packet);
+            let destination = packet[0]
+                .to_i64()
+                .and_then(|address| usize::try_from(address).ok())
+                .filter(|&address| address < count);
+            match destination {
+                Some(machine) => packet
+                    .into_iter()
+                    .skip(1)
+                    .map(|value| (Some(machine), value))
+                    .collect(),
+                None => packet.into_iter().map(|value| (None, value)).collect(),
+            }
+        }
+    }
+}
+
+//A bunch of machines, wired together. Each one runs until it blocks, then the next one gets a turn.
+#[derive(Clone, Debug)]
+pub struct Network<W = i64> {
+    pub(crate) machines: Vec<Vm<W>>,
+    pub(crate) topology: Topology,
+    //With a bus: the words each machine has sent so far of a packet that isn't complete yet
+    pub(crate) packets: Vec<Vec<W>>,
+    //Whatever left the network
+    pub(crate) output: Vec<W>,
+}
+
+impl<W: Word> Network<W> {
+    pub fn new(machines: Vec<Vm<W>>, topology: Topology) -> Self {
+        Network {
+            packets: vec![Vec::new(); machines.len()],
+            machines,
+            topology,
+            output: Vec::new(),
+        }
+    }
+
+    pub fn machines(&self) -> &[Vm<W>] {
+        &self.machines
+    }
+
+    pub fn machine_mut(&mut self, machine: usize) -> &mut Vm<W> {
+        &mut self.machines[machine]
+    }
+
+    pub fn push_input(&mut self, machine: usize, value: W) {
+        self.machines[machine].push_input(value)
+    }
+
+    //Everything that left the network so far, oldest first
+    pub fn output(&self) -> &[W] {
+        &self.output
+    }
+
+    pub fn take_output(&mut self) -> Vec<W> {
+        std::mem::take(&mut self.output)
+    }
+
+    //Sends a value a machine just output to wherever it's wired to
+    fn route(&mut self, from: usize, value: W) {
+        let count = self.machines.len();
+        for (to, value) in route(&self.topology, count, from, &mut self.packets[from], value) {
+            match to {
+                Some(machine) => self.machines[machine].push_input(value),
+                None => self.output.push(value),
+            }
+        }
+    }
+
+    //Runs every machine, in turn, until they've all halted or are all stuck waiting for input
+    pub fn run(&mut self) -> Result<NetworkOutcome, NetworkError<W>> {
+        loop {
+            let mut progress = false;
+            for machine in 0..self.machines.len() {
+                loop {
+                    let vm = &mut self.machines[machine];
+                    let steps = vm.steps();
+                    let state = vm.run().map_err(|error| NetworkError { machine, error })?;
+                    progress |= state.steps > steps;
+                    match state.reason {
+                        StopReason::Output(value) => self.route(machine, value),
+                        StopReason::Watchpoint(_) => {}
+                        StopReason::NeedsInput | StopReason::Halted => break,
+                    }
+                }
+            }
+
+            let waiting: Vec<usize> = (0..self.machines.len())
+                .filter(|&machine| !self.machines[machine].is_halted())
+                .collect();
+            if waiting.is_empty() {
+                return Ok(NetworkOutcome::Halted);
+            }
+            //A whole round where nobody could move means nobody ever will
+            if !progress {
+                return Ok(NetworkOutcome::Deadlock { waiting });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::network::*;
+
+    fn machines(program: &[i64], inputs: &[i64]) -> Vec<Vm> {
+        inputs
+            .iter()
+            .map(|&input| {
+                let mut vm = Vm::new(program.to_vec());
+                vm.push_input(input);
+                vm
+            })
+            .collect()
+    }
+
+    #[test]
+    fn chain() {
+        //Each amplifier outputs 10 * its input + its phase
+        let program = [
+            3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0,
+        ];
+        let mut network = Network::new(machines(&program, &[4, 3, 2, 1, 0]), Topology::Chain);
+        network.push_input(0, 0);
+        assert_eq!(network.run().unwrap(), NetworkOutcome::Halted);
+        assert_eq!(network.output(), [43210]);
+    }
+
+    #[test]
+    fn ring() {
+        let program = [
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1,
+            28, 1005, 28, 6, 99, 0, 0, 5,
+        ];
+        let mut network = Network::new(machines(&program, &[9, 8, 7, 6, 5]), Topology::Ring);
+        network.push_input(0, 0);
+        assert_eq!(network.run().unwrap(), NetworkOutcome::Halted);
+        assert_eq!(network.output().last(), Some(&139629729));
+
+        //Two echoes waiting for each other to say something
+        let mut network = Network::new(vec![Vm::new(vec![3i64, 0, 4, 0, 99]); 2], Topology::Ring);
+        assert_eq!(
+            network.run().unwrap(),
+            NetworkOutcome::Deadlock {
+                waiting: vec![0, 1]
+            }
+        );
+        network.push_input(0, 7);
+        assert_eq!(network.run().unwrap(), NetworkOutcome::Halted);
+        assert_eq!(network.take_output(), vec![7]);
+    }
+
+    #[test]
+    fn bus() {
+        //Machine 0 sends 42 to machine 1, which doubles it and sends it to the (nonexistent) machine 5
+        let machines = vec![
+            Vm::new(vec![104i64, 1, 104, 42, 99]),
+            Vm::new(vec![3i64, 11, 1002, 11, 2, 11, 104, 5, 4, 11, 99, 0]),
+        ];
+        let mut network = Network::new(machines, Topology::Bus { payload: 1 });
+        assert_eq!(network.run().unwrap(), NetworkOutcome::Halted);
+        assert_eq!(network.output(), [5, 84]);
+
+        let mut network = Network::new(vec![Vm::new(vec![1i64, -1, 0, 0])], Topology::Chain);
+        assert_eq!(network.run().unwrap_err().machine, 0);
+    }
+}
@@ -0,0 +1,176 @@
+use crate::word::Word;
+use std::fmt;
+use std::io::Read;
+
+//Reads programs in the usual comma-separated format puzzle inputs come in. Anything from a `#` to the end of the
+// line is a comment, and whitespace (newlines included) can go anywhere between words:
+//
+//  # Multiplies 3 by 4
+//  1002, 4, 3, 4,
+//  33
+//
+//A trailing comma is fine, but two commas in a row aren't.
+
+//Where (1-based) and why parsing failed
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+//Couldn't get a program out of a file (or stdin)
+#[derive(Debug)]
+pub enum LoadError {
+    Io(std::io::Error),
+    Parse(ParseError),
+}
+
+impl From<std::io::Error> for LoadError {
+    fn from(error: std::io::Error) -> Self {
+        LoadError::Io(error)
+    }
+}
+
+impl From<ParseError> for LoadError {
+    fn from(error: ParseError) -> Self {
+        LoadError::Parse(error)
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(error) => write!(f, "couldn't read the program: {}", error),
+            LoadError::Parse(error) => write!(f, "invalid program: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+#[derive(Debug)]
+enum Token<'a> {
+    Comma,
+    Word(&'a str),
+}
+
+//Every token in the text, along with its line and column
+fn tokenize(text: &str) -> Vec<(Token<'_>, usize, usize)> {
+    let mut tokens = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = &line[..line.find('#').unwrap_or(line.len())];
+        let mut start = None;
+        for (at, c) in line
+            .char_indices()
+            .chain(std::iter::once((line.len(), ' ')))
+        {
+            if c == ',' || c.is_whitespace() {
+                if let Some(start) = start.take() {
+                    tokens.push((Token::Word(&line[start..at]), n + 1, start + 1));
+                }
+                if c == ',' {
+                    tokens.push((Token::Comma, n + 1, at + 1));
+                }
+            } else if start.is_none() {
+                start = Some(at);
+            }
+        }
+    }
+    tokens
+}
+
+pub fn parse_program<W: Word>(text: &str) -> Result<Vec<W>, ParseError> {
+    let error = |line, column, message: String| ParseError {
+        line,
+        column,
+        message,
+    };
+    let mut program = Vec::new();
+    //Words and commas take turns, starting with a word
+    let mut expecting_word = true;
+    for (token, line, column) in tokenize(text) {
+        match token {
+            Token::Word(word) if expecting_word => {
+                let value = word
+                    .parse()
+                    .map_err(|_| error(line, column, format!("invalid number `{}`", word)))?;
+                program.push(value);
+            }
+            Token::Word(word) => {
+                return Err(error(
+                    line,
+                    column,
+                    format!("expected `,` before `{}`", word),
+                ))
+            }
+            Token::Comma if expecting_word => {
+                return Err(error(
+                    line,
+                    column,
+                    "expected a number before `,`".to_string(),
+                ))
+            }
+            Token::Comma => {}
+        }
+        expecting_word = !expecting_word;
+    }
+    if program.is_empty() {
+        return Err(error(1, 1, "there's no program in there".to_string()));
+    }
+    Ok(program)
+}
+
+//Reads a program from a file, or from stdin if the path is `-`
+pub fn load_program<W: Word>(path: &str) -> Result<Vec<W>, LoadError> {
+    let text = if path == "-" {
+        let mut text = String::new();
+        std::io::stdin().read_to_string(&mut text)?;
+        text
+    } else {
+        std::fs::read_to_string(path)?
+    };
+    Ok(parse_program(&text)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bigint::BigInt;
+    use crate::parse::*;
+
+    #[test]
+    fn comments_and_whitespace() {
+        let text = "# Multiplies 3 by 4\n1002, 4, +3,4,  # the 4 is patched into a 99\n\n-33\n";
+        assert_eq!(
+            parse_program::<i64>(text).unwrap(),
+            vec![1002, 4, 3, 4, -33]
+        );
+        assert_eq!(parse_program::<i64>("1,0,0,0,99,\n").unwrap().len(), 5);
+        let program: Vec<BigInt> = parse_program("99,123456789012345678901234567890").unwrap();
+        assert_eq!(program[1].to_string(), "123456789012345678901234567890");
+    }
+
+    #[test]
+    fn errors_have_positions() {
+        let error = |text| parse_program::<i64>(text).unwrap_err();
+        assert_eq!(
+            error("1,0,0,\n0,x9,99"),
+            ParseError {
+                line: 2,
+                column: 3,
+                message: "invalid number `x9`".to_string()
+            }
+        );
+        assert_eq!(error("1,,2").column, 3);
+        assert_eq!(error("1, 2 3").message, "expected `,` before `3`");
+        assert_eq!(error("# nothing\n").message, "there's no program in there");
+    }
+}
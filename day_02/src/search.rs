@@ -0,0 +1,457 @@
+use crate::compile::Compiled;
+use crate::error::{VmError, VmErrorKind};
+use crate::memory::Memory;
+use crate::vm::{StopReason, Vm, CHUNK};
+use crate::word::Word;
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering as Atomic};
+use std::sync::Mutex;
+
+//Looks for the values to poke into some cells to get the result we want out of a program, like day 2's
+// noun and verb. Candidates are tried in order: the first cell's values change slowest, like nested loops would.
+//
+//What we want is given as a comparison of each finished run against it, so that bisection knows which way to go.
+// `Equal` is a match, and for the other strategies that's all that matters.
+
+//How to go through the candidates
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Strategy {
+    //Every candidate, returning every match
+    Exhaustive,
+    //Stops at the first match
+    FirstMatch,
+    //Like `FirstMatch`, but candidates are spread across this many threads (0 for one per core).
+    // Whichever thread gets there first, it's still the first match (or crash) in order that counts.
+    Parallel { threads: usize },
+    //For when the result only goes up along one of the cells (given by its index, in the order they were added):
+    // comparisons go `Less`, then `Equal`, then `Greater` as its value moves through its domain.
+    // Every combination of the other cells is still tried, but that one gets bisected.
+    Bisect { cell: usize },
+}
+
+//A finished run: the machine as it was when it halted, and everything it output
+#[derive(Clone, Debug)]
+pub struct Outcome<W> {
+    pub vm: Vm<W>,
+    pub outputs: Vec<W>,
+}
+
+//Some candidate crashed the program
+#[derive(Clone, Debug, PartialEq)]
+pub struct Crash<W> {
+    pub values: Vec<W>,
+    pub error: VmError<W>,
+}
+
+impl<W: fmt::Display> fmt::Display for Crash<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let values: Vec<String> = self.values.iter().map(|v| v.to_string()).collect();
+        write!(f, "with {}: {}", values.join(","), self.error)
+    }
+}
+
+//What a search turned up: every match (or just the first one), and the candidates that crashed on the way there.
+// Both are in order, each as the values of the cells.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Found<W> {
+    pub matches: Vec<Vec<W>>,
+    pub crashes: Vec<Crash<W>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SearchError<W> {
+    //A candidate crashed, and the search was told to stop when that happens
+    Crashed(Crash<W>),
+    //Bisecting a cell that was never added
+    NoSuchCell(usize),
+}
+
+impl<W: fmt::Display> fmt::Display for SearchError<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SearchError::Crashed(crash) => write!(f, "{}", crash),
+            SearchError::NoSuchCell(cell) => write!(f, "there's no cell {} to bisect", cell),
+        }
+    }
+}
+
+impl<W: fmt::Debug + fmt::Display> std::error::Error for SearchError<W> {}
+
+#[derive(Clone, Debug)]
+pub struct Search<W = i64> {
+    program: Compiled<W>,
+    //The address of each cell, and the values it can take, in the order they're tried
+    cells: Vec<(usize, Vec<W>)>,
+    step_limit: Option<u64>,
+    stop_on_crash: bool,
+}
+
+impl<W: Word> Search<W> {
+    pub fn new(program: impl Into<Memory<W>>) -> Self {
+        Search {
+            program: Compiled::new(program),
+            cells: Vec::new(),
+            step_limit: None,
+            stop_on_crash: false,
+        }
+    }
+
+    //Adds a cell to try values in
+    pub fn with_cell(mut self, address: usize, values: impl IntoIterator<Item = W>) -> Self {
+        self.cells.push((address, values.into_iter().collect()));
+        self
+    }
+
+    //Every run stops with an error after this many instructions, like `Vm::with_step_limit`
+    pub fn with_step_limit(mut self, limit: u64) -> Self {
+        self.step_limit = Some(limit);
+        self
+    }
+
+    //Stop at the first crash (in order), with an error. Otherwise candidates that crash just don't match.
+    pub fn with_stop_on_crash(mut self) -> Self {
+        self.stop_on_crash = true;
+        self
+    }
+
+    //How many candidates there are in total
+    pub fn len(&self) -> usize {
+        self.cells.iter().map(|(_, values)| values.len()).product()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    //The values of the candidate with that index, for each cell
+    pub fn candidate(&self, index: usize) -> Vec<W> {
+        self.indices(index)
+            .into_iter()
+            .zip(&self.cells)
+            .map(|(index, (_, values))| values[index].clone())
+            .collect()
+    }
+
+    //Splits a candidate's index into one index per cell, the last cell changing fastest
+    fn indices(&self, mut index: usize) -> Vec<usize> {
+        let mut indices = vec![0; self.cells.len()];
+        for (n, (_, values)) in self.cells.iter().enumerate().rev() {
+            indices[n] = index % values.len();
+            index /= values.len();
+        }
+        indices
+    }
+
+    fn index(&self, indices: &[usize]) -> usize {
+        indices
+            .iter()
+            .zip(&self.cells)
+            .fold(0, |index, (&n, (_, values))| index * values.len() + n)
+    }
+
+    //Runs the program with some values in the cells, until it halts
+    pub fn outcome(&self, values: &[W]) -> Result<Outcome<W>, Crash<W>> {
+        Ok(self
+            .cancellable(values, &|| false)?
+            .expect("A run that can't be cancelled was cancelled"))
+    }
+
+    //Same as `outcome`, but gives up (with None) once `cancelled` says so. That's checked every `CHUNK` steps,
+    // so that even a candidate that loops forever stops soon after the search doesn't need it anymore.
+    fn cancellable(
+        &self,
+        values: &[W],
+        cancelled: &dyn Fn() -> bool,
+    ) -> Result<Option<Outcome<W>>, Crash<W>> {
+        let mut vm = self.program.vm();
+        //Going through `set` keeps track of which compiled ops the values land on
+        for ((address, _), value) in self.cells.iter().zip(values) {
+            vm.set(*address, value.clone());
+        }
+        let mut outputs = Vec::new();
+        let crashed = |error| Crash {
+            values: values.to_vec(),
+            error,
+        };
+        loop {
+            //Runs stop at the end of the chunk, or at the actual limit if that comes first
+            let chunk = vm.steps.saturating_add(CHUNK);
+            vm.step_limit = Some(self.step_limit.map_or(chunk, |limit| limit.min(chunk)));
+            let state = match self.program.run(&mut vm) {
+                Err(error) if matches!(error.kind, VmErrorKind::StepLimitExceeded(_)) => {
+                    match self.step_limit {
+                        Some(limit) if vm.steps >= limit => return Err(crashed(error)),
+                        _ if cancelled() => return Ok(None),
+                        _ => continue,
+                    }
+                }
+                state => state.map_err(crashed)?,
+            };
+            match state.reason {
+                StopReason::Output(value) => outputs.push(value),
+                StopReason::Watchpoint(_) => {}
+                StopReason::Halted => {
+                    vm.step_limit = self.step_limit;
+                    return Ok(Some(Outcome { vm, outputs }));
+                }
+                StopReason::NeedsInput => {
+                    return Err(crashed(vm.error(VmErrorKind::InputExhausted)))
+                }
+            }
+        }
+    }
+
+    pub fn run(
+        &self,
+        strategy: Strategy,
+        compare: impl Fn(&Outcome<W>) -> Ordering + Sync,
+    ) -> Result<Found<W>, SearchError<W>> {
+        let crashes = Mutex::new(Vec::new());
+        //How a candidate compares, or None if it crashed (or was cancelled)
+        let cancellable = |index: usize, cancelled: &dyn Fn() -> bool| match self
+            .cancellable(&self.candidate(index), cancelled)
+        {
+            Ok(Some(outcome)) => Ok(Some(compare(&outcome))),
+            Ok(None) => Ok(None),
+            Err(crash) if self.stop_on_crash => Err(SearchError::Crashed(crash)),
+            Err(crash) => {
+                crashes.lock().unwrap().push((index, crash));
+                Ok(None)
+            }
+        };
+        let check = |index: usize| cancellable(index, &|| false);
+        let mut found = Vec::new();
+        match strategy {
+            Strategy::Exhaustive | Strategy::FirstMatch => {
+                for index in 0..self.len() {
+                    if check(index)? == Some(Ordering::Equal) {
+                        found.push(index);
+                        if strategy == Strategy::FirstMatch {
+                            break;
+                        }
+                    }
+                }
+            }
+            Strategy::Bisect { cell } => {
+                let size = match self.cells.get(cell) {
+                    Some((_, values)) => values.len(),
+                    None => return Err(SearchError::NoSuchCell(cell)),
+                };
+                //Candidates with the bisected cell at its first value, one for each combination of the others
+                for index in (0..self.len()).filter(|&index| self.indices(index)[cell] == 0) {
+                    let mut indices = self.indices(index);
+                    let at = |indices: &mut Vec<usize>, n: usize| {
+                        indices[cell] = n;
+                        self.index(indices)
+                    };
+                    //The first value that isn't too small (crashes count as too big)...
+                    let (mut low, mut high) = (0, size);
+                    while low < high {
+                        let middle = (low + high) / 2;
+                        match check(at(&mut indices, middle))? {
+                            Some(Ordering::Less) => low = middle + 1,
+                            _ => high = middle,
+                        }
+                    }
+                    //...and every value from there on that's just right
+                    for n in low..size {
+                        let index = at(&mut indices, n);
+                        if check(index)? != Some(Ordering::Equal) {
+                            break;
+                        }
+                        found.push(index);
+                    }
+                }
+                found.sort();
+            }
+            Strategy::Parallel { threads } => found.extend(self.parallel(threads, &cancellable)?),
+        }
+        //Bisection can try the same candidate twice, and threads can crash past the first match
+        let mut crashes = crashes.into_inner().unwrap();
+        crashes.sort_by_key(|&(index, _)| index);
+        crashes.dedup_by_key(|&mut (index, _)| index);
+        if let (Strategy::Parallel { .. }, Some(&first)) = (strategy, found.first()) {
+            crashes.retain(|&(index, _)| index < first);
+        }
+        Ok(Found {
+            matches: found
+                .into_iter()
+                .map(|index| self.candidate(index))
+                .collect(),
+            crashes: crashes.into_iter().map(|(_, crash)| crash).collect(),
+        })
+    }
+
+    //The index of the first match, trying candidates on many threads at once
+    fn parallel(
+        &self,
+        threads: usize,
+        check: &(impl Fn(usize, &dyn Fn() -> bool) -> Result<Option<Ordering>, SearchError<W>> + Sync),
+    ) -> Result<Option<usize>, SearchError<W>> {
+        let threads = match threads {
+            0 => std::thread::available_parallelism().map_or(1, |threads| threads.get()),
+            threads => threads,
+        };
+        //Candidates are handed out in order, so once something's found, everything before it was already
+        // handed out, and everything after it doesn't need to be tried anymore
+        let next = AtomicUsize::new(0);
+        let stop = AtomicUsize::new(self.len());
+        let results = Mutex::new(Vec::new());
+        std::thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, Atomic::Relaxed);
+                    if index >= stop.load(Atomic::Relaxed) {
+                        return;
+                    }
+                    //Once something earlier turns up, this one doesn't matter anymore
+                    let cancelled = || stop.load(Atomic::Relaxed) < index;
+                    let result = match check(index, &cancelled) {
+                        Ok(Some(Ordering::Equal)) => Ok(()),
+                        Ok(_) => continue,
+                        Err(error) => Err(error),
+                    };
+                    stop.fetch_min(index, Atomic::Relaxed);
+                    results.lock().unwrap().push((index, result));
+                });
+            }
+        });
+        //Threads can find several things before they notice someone else found something earlier
+        let first = results
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .min_by_key(|&(index, _)| index);
+        match first {
+            Some((index, result)) => result.map(|()| Some(index)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::search::*;
+
+    fn day_02() -> Search {
+        let program = crate::parse_program(include_str!("input.txt")).unwrap();
+        Search::new(program)
+            .with_cell(1, 0..100)
+            .with_cell(2, 0..100)
+    }
+
+    fn matches<W>(found: Result<Found<W>, SearchError<W>>) -> Vec<Vec<W>> {
+        found.ok().unwrap().matches
+    }
+
+    #[test]
+    fn strategies() {
+        let search = day_02();
+        let compare = |outcome: &Outcome<i64>| outcome.vm.memory().get(0).cmp(&19690720);
+        let answer = vec![vec![45, 59]];
+        assert_eq!(matches(search.run(Strategy::FirstMatch, compare)), answer);
+        assert_eq!(
+            matches(search.run(Strategy::Bisect { cell: 0 }, compare)),
+            answer
+        );
+        assert_eq!(
+            matches(search.run(Strategy::Parallel { threads: 0 }, compare)),
+            answer
+        );
+        //Cell 0 goes up with the verb too, so it can be bisected just as well
+        let compare =
+            |outcome: &Outcome<i64>| outcome.vm.memory().get(0).cmp(&(432000 * 3 + 250661));
+        let all = matches(search.run(Strategy::Exhaustive, compare));
+        assert_eq!(all, vec![vec![3, 0]]);
+        assert_eq!(
+            matches(search.run(Strategy::Bisect { cell: 1 }, compare)),
+            all
+        );
+        assert_eq!(
+            search.run(Strategy::Bisect { cell: 2 }, compare),
+            Err(SearchError::NoSuchCell(2))
+        );
+    }
+
+    #[test]
+    fn parallel() {
+        //Lots of matches, and the lowest one has to win however many threads are racing for it
+        let search = day_02();
+        let compare = |outcome: &Outcome<i64>| match outcome.vm.memory().get(0) >= 19690720 {
+            true => Ordering::Equal,
+            false => Ordering::Less,
+        };
+        for &threads in &[1, 3, 16] {
+            let found = search.run(Strategy::Parallel { threads }, compare);
+            assert_eq!(matches(found), vec![vec![45, 59]]);
+        }
+        let compare = |_: &Outcome<i64>| Ordering::Less;
+        assert_eq!(
+            matches(search.run(Strategy::Parallel { threads: 4 }, compare)),
+            Vec::<Vec<i64>>::new()
+        );
+
+        //Every candidate after the first loops forever, and threads still busy with them have to give up
+        let search = Search::new(vec![1105i64, 0, 0, 99]).with_cell(1, vec![0, 1, 1, 1]);
+        let compare = |_: &Outcome<i64>| Ordering::Equal;
+        assert_eq!(
+            matches(search.run(Strategy::Parallel { threads: 4 }, compare)),
+            vec![vec![0]]
+        );
+
+        //Only crashes before the first match are reported, however far other threads got
+        let program = vec![1i64, 0, 10, 11, 102, 2, 1, 11, 4, 11, 99, 0];
+        let search = Search::new(program).with_cell(1, vec![0, -1, 1, 2, -1, 3, -1]);
+        let compare = |outcome: &Outcome<i64>| outcome.outputs[0].cmp(&4);
+        let found = search.run(Strategy::Parallel { threads: 4 }, compare);
+        assert_eq!(found, search.run(Strategy::FirstMatch, compare));
+        let found = found.unwrap();
+        assert_eq!(found.matches, vec![vec![2]]);
+        assert_eq!(found.crashes.len(), 1);
+
+        //Stopping at crashes, it's the first crash before the first match that gets reported
+        let search = search.with_stop_on_crash();
+        let error = search
+            .run(Strategy::Parallel { threads: 4 }, compare)
+            .unwrap_err();
+        assert_eq!(
+            error,
+            search.run(Strategy::FirstMatch, compare).unwrap_err()
+        );
+        let compare = |outcome: &Outcome<i64>| outcome.outputs[0].cmp(&0);
+        assert_eq!(
+            matches(search.run(Strategy::Parallel { threads: 4 }, compare)),
+            vec![vec![0]]
+        );
+    }
+
+    #[test]
+    fn outputs_and_crashes() {
+        //Reads from the address in cell 1 (for no reason), then outputs twice its value
+        let program = vec![1i64, 0, 10, 11, 102, 2, 1, 11, 4, 11, 99, 0];
+        let compare = |outcome: &Outcome<i64>| outcome.outputs[..].cmp(&[4][..]);
+        let search = Search::new(program.clone()).with_cell(1, -1..=3);
+        let found = search.run(Strategy::Exhaustive, compare).unwrap();
+        assert_eq!(found.matches, vec![vec![2]]);
+        let crash = &found.crashes[0];
+        assert_eq!(
+            (&crash.values, &crash.error.kind),
+            (&vec![-1], &VmErrorKind::ReadOutOfBounds(-1))
+        );
+
+        let search = search.with_stop_on_crash();
+        match search.run(Strategy::Exhaustive, compare) {
+            Err(SearchError::Crashed(crash)) => assert_eq!(crash.values, vec![-1]),
+            other => panic!("expected a crash, got {:?}", other),
+        }
+
+        let search = Search::new(program).with_cell(1, 0..=3);
+        assert_eq!(
+            matches(search.run(Strategy::Exhaustive, compare)),
+            vec![vec![2]]
+        );
+        assert_eq!(search.len(), 4);
+        assert_eq!(search.candidate(3), vec![3]);
+    }
+}
@@ -0,0 +1,257 @@
+use crate::memory::Memory;
+use crate::vm::Vm;
+use crate::word::Word;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+//Snapshots are plain text, one field per line, so they can be diffed and written by hand for test fixtures:
+//
+//  intcode-snapshot 1
+//  ip 4
+//  relative_base 0
+//  steps 1
+//  halted false
+//  input 5,6
+//  output
+//  memory 0 1,0,0,3,99
+//  memory 2000000 42
+//
+//Each `memory` line is a run of consecutive cells, starting at the given address.
+// Words are written in full, so loading a snapshot gives back exactly the same machine.
+const HEADER: &str = "intcode-snapshot";
+const VERSION: u32 = 1;
+
+//Loading went wrong: either we couldn't read, what we read doesn't make sense (the line is 1-based),
+// or a field we need was never there
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(std::io::Error),
+    Invalid { line: usize, message: String },
+    Missing(&'static str),
+}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(error: std::io::Error) -> Self {
+        SnapshotError::Io(error)
+    }
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(error) => write!(f, "couldn't read snapshot: {}", error),
+            SnapshotError::Invalid { line, message } => {
+                write!(f, "invalid snapshot (line {}): {}", line, message)
+            }
+            SnapshotError::Missing(field) => write!(f, "invalid snapshot: missing `{}`", field),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+fn join<'a, W: Word>(words: impl Iterator<Item = &'a W>) -> String {
+    let words: Vec<String> = words.map(|word| word.to_string()).collect();
+    words.join(",")
+}
+
+fn parse<T: std::str::FromStr>(text: &str, line: usize) -> Result<T, SnapshotError> {
+    text.parse().map_err(|_| SnapshotError::Invalid {
+        line,
+        message: format!("invalid value `{}`", text),
+    })
+}
+
+impl<W: Word> Vm<W> {
+    //Writes out everything needed to pick up exactly where we are.
+    // Settings like step limits aren't part of the machine, so they aren't saved.
+    pub fn save(&self, out: &mut impl Write) -> std::io::Result<()> {
+        writeln!(out, "{} {}", HEADER, VERSION)?;
+        writeln!(out, "ip {}", self.ip)?;
+        writeln!(out, "relative_base {}", self.relative_base)?;
+        writeln!(out, "steps {}", self.steps)?;
+        writeln!(out, "halted {}", self.halted)?;
+        writeln!(out, "input {}", join(self.input.iter()))?;
+        writeln!(out, "output {}", join(self.output.iter()))?;
+
+        //Group cells into runs of consecutive addresses
+        let mut cells = self.memory.iter().peekable();
+        while let Some((start, first)) = cells.next() {
+            let mut run = vec![first];
+            while let Some(&(address, word)) = cells.peek() {
+                if address != start + run.len() {
+                    break;
+                }
+                run.push(word);
+                cells.next();
+            }
+            writeln!(out, "memory {} {}", start, join(run.into_iter()))?;
+        }
+        Ok(())
+    }
+
+    pub fn save_to_file(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+        self.save(&mut out)?;
+        out.flush()
+    }
+
+    pub fn load(reader: impl BufRead) -> Result<Self, SnapshotError> {
+        let mut vm = Vm::new(Memory::new());
+        let mut seen = Vec::new();
+        let mut header = false;
+        for (n, line) in reader.lines().enumerate() {
+            let line = line?;
+            let invalid = |message: String| SnapshotError::Invalid {
+                line: n + 1,
+                message,
+            };
+            let (key, value) = match line.find(' ') {
+                Some(at) => (&line[..at], &line[at + 1..]),
+                None => (line.as_str(), ""),
+            };
+
+            if n == 0 {
+                if key != HEADER {
+                    return Err(invalid("not an Intcode snapshot".to_string()));
+                }
+                if value != VERSION.to_string() {
+                    return Err(invalid(format!("unsupported version `{}`", value)));
+                }
+                header = true;
+                continue;
+            }
+            if key != "memory" && seen.contains(&key.to_string()) {
+                return Err(invalid(format!("`{}` appears twice", key)));
+            }
+            seen.push(key.to_string());
+
+            let words = |value: &str| -> Result<Vec<W>, SnapshotError> {
+                value
+                    .split(',')
+                    .filter(|word| !word.is_empty())
+                    .map(|word| parse(word, n + 1))
+                    .collect()
+            };
+            match key {
+                "ip" => vm.ip = parse(value, n + 1)?,
+                "relative_base" => vm.relative_base = parse(value, n + 1)?,
+                "steps" => vm.steps = parse(value, n + 1)?,
+                "halted" => vm.halted = parse(value, n + 1)?,
+                "input" => vm.input = words(value)?.into_iter().collect::<VecDeque<W>>(),
+                "output" => vm.output = words(value)?.into_iter().collect::<VecDeque<W>>(),
+                "memory" => {
+                    let (start, cells) = value.split_at(value.find(' ').unwrap_or(value.len()));
+                    let start: usize = parse(start, n + 1)?;
+                    for (offset, word) in words(cells.trim())?.into_iter().enumerate() {
+                        let address = start.checked_add(offset).ok_or_else(|| {
+                            invalid("memory runs past the last address".to_string())
+                        })?;
+                        vm.memory.set(address, word);
+                    }
+                }
+                _ => return Err(invalid(format!("unknown field `{}`", key))),
+            }
+        }
+
+        if !header {
+            return Err(SnapshotError::Missing(HEADER));
+        }
+        for key in &["ip", "relative_base", "steps", "halted", "input", "output"] {
+            if !seen.iter().any(|seen| seen == key) {
+                return Err(SnapshotError::Missing(key));
+            }
+        }
+        Ok(vm)
+    }
+
+    pub fn load_from_file(path: impl AsRef<Path>) -> Result<Self, SnapshotError> {
+        let file = std::fs::File::open(path)?;
+        Vm::load(std::io::BufReader::new(file))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bigint::BigInt;
+    use crate::snapshot::SnapshotError;
+    use crate::vm::{StopReason, Vm};
+
+    //Sums up its inputs until it gets a 0, outputting the running total each time
+    const PROGRAM: [i64; 16] = [3, 14, 1005, 14, 6, 99, 1, 14, 15, 15, 4, 15, 1105, 1, 0, 0];
+
+    #[test]
+    fn resuming_is_identical() {
+        let mut vm = Vm::new(PROGRAM.to_vec());
+        vm.memory_mut().set(3_000_000, 7);
+        for input in &[5, 6] {
+            vm.push_input(*input);
+        }
+        assert_eq!(vm.run().unwrap().reason, StopReason::Output(5));
+        vm.push_input(0);
+
+        let mut saved = Vec::new();
+        vm.save(&mut saved).unwrap();
+        let mut copy = Vm::load(&saved[..]).unwrap();
+        let mut resaved = Vec::new();
+        copy.save(&mut resaved).unwrap();
+        assert_eq!(
+            String::from_utf8(saved).unwrap(),
+            String::from_utf8(resaved).unwrap()
+        );
+
+        loop {
+            let (expected, actual) = (vm.run().unwrap(), copy.run().unwrap());
+            assert_eq!(expected, actual);
+            if expected.reason == StopReason::Halted {
+                break;
+            }
+        }
+        assert_eq!(vm.memory(), copy.memory());
+    }
+
+    #[test]
+    fn fixtures() {
+        let fixture = "\
+intcode-snapshot 1
+ip 6
+relative_base 0
+steps 7
+halted false
+input
+output 3
+memory 0 3,14,1005,14,6,99,1,14,15,15,4,15,1105,1,0,0
+memory 14 123456789012345678901234567890,1
+";
+        let mut vm: Vm<BigInt> = Vm::load(fixture.as_bytes()).unwrap();
+        let total = vm.run().unwrap().reason;
+        assert_eq!(
+            total,
+            StopReason::Output("123456789012345678901234567891".parse().unwrap())
+        );
+        assert_eq!(vm.take_output(), vec![BigInt::from(3)]);
+
+        let error = Vm::<i64>::load("intcode-snapshot 2\n".as_bytes()).unwrap_err();
+        match error {
+            SnapshotError::Invalid { line, message } => {
+                assert_eq!((line, message.as_str()), (1, "unsupported version `2`"))
+            }
+            _ => panic!("expected an invalid snapshot"),
+        }
+        let error =
+            Vm::<i64>::load("intcode-snapshot 1\nmemory 18446744073709551615 1,2\n".as_bytes());
+        match error.unwrap_err() {
+            SnapshotError::Invalid { line, .. } => assert_eq!(line, 2),
+            _ => panic!("expected an invalid snapshot"),
+        }
+        let error = Vm::<i64>::load("intcode-snapshot 1\n".as_bytes()).unwrap_err();
+        assert_eq!(error.to_string(), "invalid snapshot: missing `ip`");
+        let error = Vm::<i64>::load("".as_bytes()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid snapshot: missing `intcode-snapshot`"
+        );
+    }
+}
@@ -0,0 +1,623 @@
+use crate::error::VmErrorKind;
+use crate::instruction::{IntcodeInstruction, Param, ParamMode};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Range;
+
+//Runs a program with some cells standing for unknowns instead of values, to find out what it computes in terms
+// of them. Everything the program does with plain values happens just like in the VM, and whatever involves an
+// unknown gets recorded as an expression instead:
+//
+//  cell 0 = 432000*noun + verb + 250661
+//
+//Anything that needs a value right away (an opcode, a jump, a write address) can't depend on an unknown, and
+// the program is only followed down one path, so this is for straight-line programs like day 2's.
+
+//What a cell holds: an actual value, or how it was computed from the unknowns
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Const(i64),
+    Var(String),
+    Add(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    LessThan(Box<Expr>, Box<Expr>),
+    Equals(Box<Expr>, Box<Expr>),
+    //Whatever is at an address that depends on an unknown
+    Load(Box<Expr>),
+}
+
+impl Expr {
+    //The sum or product, as a polynomial if that's what it is. None if it isn't one (because it compares or
+    // loads something), or if its coefficients get too big.
+    pub fn polynomial(&self) -> Option<Polynomial> {
+        match self {
+            Expr::Const(value) => Some(Polynomial::constant(*value as i128)),
+            Expr::Var(name) => Some(Polynomial::var(name)),
+            Expr::Add(lhs, rhs) => lhs.polynomial()?.add(&rhs.polynomial()?),
+            Expr::Mul(lhs, rhs) => lhs.polynomial()?.mul(&rhs.polynomial()?),
+            _ => None,
+        }
+    }
+
+    //The value, if it doesn't actually depend on any unknowns (like `noun*0`)
+    pub fn value(&self) -> Option<i64> {
+        match self {
+            Expr::Const(value) => Some(*value),
+            _ => i64::try_from(self.polynomial()?.as_constant()?).ok(),
+        }
+    }
+}
+
+//Polynomials are shown simplified, and everything else as it was built
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(polynomial) = self.polynomial() {
+            return write!(f, "{}", polynomial);
+        }
+        match self {
+            Expr::Const(value) => write!(f, "{}", value),
+            Expr::Var(name) => write!(f, "{}", name),
+            Expr::Add(lhs, rhs) => write!(f, "({} + {})", lhs, rhs),
+            Expr::Mul(lhs, rhs) => write!(f, "({} * {})", lhs, rhs),
+            Expr::LessThan(lhs, rhs) => write!(f, "({} < {})", lhs, rhs),
+            Expr::Equals(lhs, rhs) => write!(f, "({} == {})", lhs, rhs),
+            Expr::Load(address) => write!(f, "[{}]", address),
+        }
+    }
+}
+
+//A sum of terms, each a coefficient times some unknowns (sorted by name, and repeated for powers)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Polynomial {
+    terms: BTreeMap<Vec<String>, i128>,
+}
+
+impl Polynomial {
+    pub fn constant(value: i128) -> Self {
+        let mut polynomial = Polynomial::default();
+        if value != 0 {
+            polynomial.terms.insert(Vec::new(), value);
+        }
+        polynomial
+    }
+
+    pub fn var(name: &str) -> Self {
+        let mut polynomial = Polynomial::default();
+        polynomial.terms.insert(vec![name.to_string()], 1);
+        polynomial
+    }
+
+    fn add_term(&mut self, monomial: Vec<String>, coefficient: i128) -> Option<()> {
+        let sum = self
+            .terms
+            .get(&monomial)
+            .unwrap_or(&0)
+            .checked_add(coefficient)?;
+        if sum == 0 {
+            self.terms.remove(&monomial);
+        } else {
+            self.terms.insert(monomial, sum);
+        }
+        Some(())
+    }
+
+    pub fn add(&self, other: &Polynomial) -> Option<Polynomial> {
+        let mut sum = self.clone();
+        for (monomial, &coefficient) in &other.terms {
+            sum.add_term(monomial.clone(), coefficient)?;
+        }
+        Some(sum)
+    }
+
+    pub fn mul(&self, other: &Polynomial) -> Option<Polynomial> {
+        let mut product = Polynomial::default();
+        for (lhs, &a) in &self.terms {
+            for (rhs, &b) in &other.terms {
+                let mut monomial: Vec<String> = lhs.iter().chain(rhs).cloned().collect();
+                monomial.sort();
+                product.add_term(monomial, a.checked_mul(b)?)?;
+            }
+        }
+        Some(product)
+    }
+
+    //The highest number of unknowns multiplied together in a term (0 for constants)
+    pub fn degree(&self) -> usize {
+        self.terms.keys().map(Vec::len).max().unwrap_or(0)
+    }
+
+    //Something like `a*x + b*y + c`
+    pub fn is_affine(&self) -> bool {
+        self.degree() <= 1
+    }
+
+    pub fn as_constant(&self) -> Option<i128> {
+        match self.degree() {
+            0 => Some(self.coefficient(&[])),
+            _ => None,
+        }
+    }
+
+    //The coefficient of a term, given the unknowns in it (in any order)
+    pub fn coefficient(&self, unknowns: &[&str]) -> i128 {
+        let mut monomial: Vec<String> = unknowns.iter().map(|name| name.to_string()).collect();
+        monomial.sort();
+        self.terms.get(&monomial).cloned().unwrap_or(0)
+    }
+
+    //Every unknown that appears, in name order
+    pub fn unknowns(&self) -> Vec<&str> {
+        let mut unknowns: Vec<&str> = self.terms.keys().flatten().map(String::as_str).collect();
+        unknowns.sort();
+        unknowns.dedup();
+        unknowns
+    }
+
+    //Plugs in values for the unknowns, given by name. None if one is missing, or on overflow.
+    pub fn eval(&self, values: &HashMap<&str, i64>) -> Option<i128> {
+        self.terms
+            .iter()
+            .try_fold(0i128, |sum, (monomial, &coefficient)| {
+                let term = monomial.iter().try_fold(coefficient, |product, name| {
+                    product.checked_mul(*values.get(name.as_str())? as i128)
+                })?;
+                sum.checked_add(term)
+            })
+    }
+
+    //Every assignment of the unknowns within their domains that makes the polynomial equal to the target, in
+    // order. The values come in the same order as the domains. None if some unknown has no domain.
+    //
+    //When it's linear in one of the unknowns, that one gets solved for instead of tried, so for `a*noun + verb + c`
+    // that's 100 tries instead of 10,000. Otherwise every combination gets evaluated, which is still a lot quicker
+    // than running the program for each.
+    pub fn solve(&self, target: i64, domains: &[(&str, Range<i64>)]) -> Option<Vec<Vec<i64>>> {
+        if self
+            .unknowns()
+            .iter()
+            .any(|unknown| domains.iter().all(|(name, _)| name != unknown))
+        {
+            return None;
+        }
+        //The last unknown that never shows up squared or multiplied by itself
+        let linear = domains.iter().rposition(|(name, _)| {
+            let count = |monomial: &Vec<String>| monomial.iter().filter(|x| x == name).count();
+            self.terms.keys().any(|monomial| count(monomial) == 1)
+                && self.terms.keys().all(|monomial| count(monomial) <= 1)
+        });
+        let mut solutions = Vec::new();
+        let mut values = vec![0; domains.len()];
+        let target = target as i128;
+        enumerate(domains, linear, 0, &mut values, &mut |values| {
+            let assignment = |values: &[i64]| -> HashMap<&str, i64> {
+                domains
+                    .iter()
+                    .map(|(name, _)| *name)
+                    .zip(values.iter().cloned())
+                    .collect()
+            };
+            match linear {
+                Some(x) => {
+                    //p = a*x + b, where a and b don't depend on x: evaluating with x at 0 and 1 gets us both
+                    let mut values = values.to_vec();
+                    values[x] = 0;
+                    let b = self.eval(&assignment(&values))?;
+                    values[x] = 1;
+                    let a = self.eval(&assignment(&values))?.checked_sub(b)?;
+                    let range = &domains[x].1;
+                    if a == 0 {
+                        if b == target {
+                            for value in range.clone() {
+                                values[x] = value;
+                                solutions.push(values.clone());
+                            }
+                        }
+                    } else if target.checked_sub(b)?.checked_rem(a)? == 0 {
+                        let value = (target - b) / a;
+                        if value >= range.start as i128 && value < range.end as i128 {
+                            values[x] = value as i64;
+                            solutions.push(values);
+                        }
+                    }
+                }
+                None => {
+                    if self.eval(&assignment(values))? == target {
+                        solutions.push(values.to_vec());
+                    }
+                }
+            }
+            Some(())
+        });
+        solutions.sort();
+        Some(solutions)
+    }
+}
+
+//Calls back with every combination of values, except for the skipped unknown (which is left alone)
+fn enumerate(
+    domains: &[(&str, Range<i64>)],
+    skip: Option<usize>,
+    at: usize,
+    values: &mut Vec<i64>,
+    each: &mut impl FnMut(&[i64]) -> Option<()>,
+) {
+    if at == domains.len() {
+        //Overflowing combinations just aren't solutions
+        let _ = each(values);
+    } else if Some(at) == skip {
+        enumerate(domains, skip, at + 1, values, each);
+    } else {
+        for value in domains[at].1.clone() {
+            values[at] = value;
+            enumerate(domains, skip, at + 1, values, each);
+        }
+    }
+}
+
+//Biggest terms first, the constant last: `3*x*y + 2*x - y + 5`
+impl fmt::Display for Polynomial {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut terms: Vec<_> = self.terms.iter().collect();
+        terms.sort_by_key(|(monomial, _)| (Reverse(monomial.len()), monomial.to_vec()));
+        if terms.is_empty() {
+            return write!(f, "0");
+        }
+        for (n, (monomial, &coefficient)) in terms.into_iter().enumerate() {
+            let sign = if coefficient < 0 { "-" } else { "+" };
+            match n {
+                0 if coefficient < 0 => write!(f, "-")?,
+                0 => {}
+                _ => write!(f, " {} ", sign)?,
+            }
+            let magnitude = coefficient.abs();
+            if monomial.is_empty() {
+                write!(f, "{}", magnitude)?;
+            } else if magnitude == 1 {
+                write!(f, "{}", monomial.join("*"))?;
+            } else {
+                write!(f, "{}*{}", magnitude, monomial.join("*"))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+//Why symbolic execution stopped before the program halted
+#[derive(Clone, Debug, PartialEq)]
+pub enum SymbolicError {
+    //The program crashed, whatever the unknowns are
+    Crashed { ip: usize, kind: VmErrorKind<i64> },
+    //Something that needs an actual value got an expression
+    Intractable { ip: usize, reason: &'static str },
+}
+
+impl fmt::Display for SymbolicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SymbolicError::Crashed { ip, kind } => write!(f, "{} (ip {})", kind, ip),
+            SymbolicError::Intractable { ip, reason } => {
+                write!(f, "{} depends on an unknown (ip {})", reason, ip)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SymbolicError {}
+
+//A machine whose memory holds expressions. Only i64 programs, since that's what puzzle inputs are.
+#[derive(Clone, Debug)]
+pub struct Symbolic {
+    //Sparse, like `Memory`, since programs can write anywhere. Loaded cells are all there, even zeroes.
+    memory: BTreeMap<usize, Expr>,
+    ip: usize,
+    relative_base: i64,
+    steps: u64,
+    step_limit: Option<u64>,
+    outputs: Vec<Expr>,
+}
+
+impl Symbolic {
+    pub fn new(program: &[i64]) -> Self {
+        Symbolic {
+            memory: program
+                .iter()
+                .enumerate()
+                .map(|(address, &value)| (address, Expr::Const(value)))
+                .collect(),
+            ip: 0,
+            relative_base: 0,
+            steps: 0,
+            step_limit: None,
+            outputs: Vec::new(),
+        }
+    }
+
+    //Makes a cell stand for an unknown, instead of whatever it held
+    pub fn with_unknown(mut self, address: usize, name: &str) -> Self {
+        self.set(address, Expr::Var(name.to_string()));
+        self
+    }
+
+    //Stop with an error after executing this many instructions, like `Vm::with_step_limit`
+    pub fn with_step_limit(mut self, limit: u64) -> Self {
+        self.step_limit = Some(limit);
+        self
+    }
+
+    pub fn get(&self, address: usize) -> Expr {
+        self.memory.get(&address).cloned().unwrap_or(Expr::Const(0))
+    }
+
+    fn set(&mut self, address: usize, value: Expr) {
+        self.memory.insert(address, value);
+    }
+
+    //One past the highest address that has been written to (or loaded), like `Memory::len`
+    fn len(&self) -> usize {
+        self.memory
+            .keys()
+            .next_back()
+            .map_or(0, |&address| address + 1)
+    }
+
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    //Everything the program output so far, oldest first
+    pub fn outputs(&self) -> &[Expr] {
+        &self.outputs
+    }
+
+    fn crashed(&self, kind: VmErrorKind<i64>) -> SymbolicError {
+        SymbolicError::Crashed { ip: self.ip, kind }
+    }
+
+    fn intractable(&self, reason: &'static str) -> SymbolicError {
+        SymbolicError::Intractable {
+            ip: self.ip,
+            reason,
+        }
+    }
+
+    //Where a parameter points to, which is an expression when it depends on an unknown
+    fn address(
+        &self,
+        param: &Param<i64>,
+        word: &Expr,
+    ) -> Result<Result<usize, Expr>, VmErrorKind<i64>> {
+        let address = match param.mode {
+            ParamMode::Relative => Expr::Add(
+                Box::new(Expr::Const(self.relative_base)),
+                Box::new(word.clone()),
+            ),
+            _ => word.clone(),
+        };
+        match address.value() {
+            Some(value) => usize::try_from(value)
+                .map(Ok)
+                .map_err(|_| VmErrorKind::ReadOutOfBounds(value)),
+            None => Ok(Err(address)),
+        }
+    }
+
+    fn read(&self, param: &Param<i64>, word: &Expr) -> Result<Expr, SymbolicError> {
+        match param.mode {
+            ParamMode::Immediate => Ok(word.clone()),
+            _ => match self
+                .address(param, word)
+                .map_err(|kind| self.crashed(kind))?
+            {
+                Ok(address) => Ok(self.get(address)),
+                Err(address) => Ok(Expr::Load(Box::new(address))),
+            },
+        }
+    }
+
+    fn write(&mut self, param: &Param<i64>, word: &Expr, value: Expr) -> Result<(), SymbolicError> {
+        let address = match self.address(param, word) {
+            Ok(Ok(address)) => address,
+            Ok(Err(_)) => return Err(self.intractable("a write address")),
+            Err(VmErrorKind::ReadOutOfBounds(address)) => {
+                return Err(self.crashed(VmErrorKind::WriteOutOfBounds(address)))
+            }
+            Err(kind) => return Err(self.crashed(kind)),
+        };
+        self.set(address, value);
+        Ok(())
+    }
+
+    //Something that has to be an actual value
+    fn value(&self, expr: &Expr, what: &'static str) -> Result<i64, SymbolicError> {
+        expr.value().ok_or_else(|| self.intractable(what))
+    }
+
+    //Runs until the program halts
+    pub fn run(&mut self) -> Result<(), SymbolicError> {
+        loop {
+            if let Some(limit) = self.step_limit {
+                if self.steps >= limit {
+                    return Err(self.crashed(VmErrorKind::StepLimitExceeded(limit)));
+                }
+            }
+            //The instruction's shape only depends on its first word. Operands are decoded with their own position as
+            // a placeholder value, so that we can tell which word they actually are.
+            let words: Vec<Expr> = (self.ip..self.len().min(self.ip.saturating_add(4)))
+                .map(|address| self.get(address))
+                .collect();
+            let mut shape: Vec<i64> = (0..words.len() as i64).collect();
+            if let Some(first) = words.first() {
+                shape[0] = self.value(first, "an opcode")?;
+            }
+            let instruction = IntcodeInstruction::new(&shape).map_err(|kind| self.crashed(kind))?;
+            let word = |param: &Param<i64>| &words[param.value as usize];
+            let mut next = self.ip + instruction.size();
+            match &instruction {
+                IntcodeInstruction::Add { lhs, rhs, dest }
+                | IntcodeInstruction::Mul { lhs, rhs, dest }
+                | IntcodeInstruction::LessThan { lhs, rhs, dest }
+                | IntcodeInstruction::Equals { lhs, rhs, dest } => {
+                    let lhs = self.read(lhs, word(lhs))?;
+                    let rhs = self.read(rhs, word(rhs))?;
+                    let value = match (lhs.value(), rhs.value()) {
+                        //Plain values get computed right away, just like the VM would
+                        (Some(a), Some(b)) => Expr::Const(
+                            match instruction {
+                                IntcodeInstruction::Add { .. } => a.checked_add(b),
+                                IntcodeInstruction::Mul { .. } => a.checked_mul(b),
+                                IntcodeInstruction::LessThan { .. } => Some((a < b) as i64),
+                                _ => Some((a == b) as i64),
+                            }
+                            .ok_or_else(|| self.crashed(VmErrorKind::Overflow))?,
+                        ),
+                        _ => {
+                            let (lhs, rhs) = (Box::new(lhs), Box::new(rhs));
+                            match instruction {
+                                IntcodeInstruction::Add { .. } => Expr::Add(lhs, rhs),
+                                IntcodeInstruction::Mul { .. } => Expr::Mul(lhs, rhs),
+                                IntcodeInstruction::LessThan { .. } => Expr::LessThan(lhs, rhs),
+                                _ => Expr::Equals(lhs, rhs),
+                            }
+                        }
+                    };
+                    self.write(dest, word(dest), value)?;
+                }
+                IntcodeInstruction::Input { .. } => return Err(self.intractable("input")),
+                IntcodeInstruction::Output { src } => {
+                    let value = self.read(src, word(src))?;
+                    self.outputs.push(value);
+                }
+                IntcodeInstruction::JumpIfTrue { cond, target }
+                | IntcodeInstruction::JumpIfFalse { cond, target } => {
+                    let cond = self.value(&self.read(cond, word(cond))?, "a jump condition")?;
+                    let jump = match instruction {
+                        IntcodeInstruction::JumpIfTrue { .. } => cond != 0,
+                        _ => cond == 0,
+                    };
+                    if jump {
+                        let target =
+                            self.value(&self.read(target, word(target))?, "a jump target")?;
+                        next = usize::try_from(target)
+                            .map_err(|_| self.crashed(VmErrorKind::InvalidJump(target)))?;
+                    }
+                }
+                IntcodeInstruction::AdjustBase { offset } => {
+                    let offset =
+                        self.value(&self.read(offset, word(offset))?, "a relative base")?;
+                    self.relative_base = self
+                        .relative_base
+                        .checked_add(offset)
+                        .ok_or_else(|| self.crashed(VmErrorKind::Overflow))?;
+                }
+                IntcodeInstruction::Halt => return Ok(()),
+            }
+            self.ip = next;
+            self.steps += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::symbolic::*;
+
+    #[test]
+    fn day_02() {
+        let program = crate::parse_program(include_str!("input.txt")).unwrap();
+        let mut symbolic = Symbolic::new(&program)
+            .with_unknown(1, "noun")
+            .with_unknown(2, "verb");
+        symbolic.run().unwrap();
+        let polynomial = symbolic.get(0).polynomial().unwrap();
+        assert!(polynomial.is_affine());
+        assert_eq!(polynomial.coefficient(&["verb"]), 1);
+        let domains = [("noun", 0..100), ("verb", 0..100)];
+        assert_eq!(
+            polynomial.solve(19690720, &domains),
+            Some(vec![vec![45, 59]])
+        );
+        assert_eq!(
+            polynomial.solve(polynomial.coefficient(&[]) as i64, &domains),
+            Some(vec![vec![0, 0]])
+        );
+
+        //Memory is sparse, so writing far away is fine
+        let mut symbolic = Symbolic::new(&[1101, 1, 1, 1_000_000_000_000, 99]);
+        symbolic.run().unwrap();
+        assert_eq!(symbolic.get(1_000_000_000_000), Expr::Const(2));
+    }
+
+    #[test]
+    fn simplified() {
+        let program = [
+            2i64, 30, 31, 32, //[32] = x*y
+            1002, 30, 4, 33, //[33] = 4*x
+            1, 32, 33, 34, //[34] = x*y + 4*x
+            1002, 30, 0, 35, //[35] = 0
+            1001, 35, 3, 35, //[35] = 3
+            1002, 30, -4, 36, //[36] = -4*x
+            1, 34, 36, 36, //[36] = x*y
+            99, 0, 0,
+        ];
+        let mut symbolic = Symbolic::new(&program)
+            .with_unknown(30, "x")
+            .with_unknown(31, "y");
+        symbolic.run().unwrap();
+        assert_eq!(symbolic.get(34).to_string(), "x*y + 4*x");
+        assert_eq!(symbolic.get(35), Expr::Const(3));
+        assert_eq!(symbolic.get(36).to_string(), "x*y");
+        assert_eq!(symbolic.steps(), 7);
+    }
+
+    #[test]
+    fn polynomials() {
+        let x = Polynomial::var("x");
+        let y = Polynomial::var("y");
+        let p = x
+            .mul(&y)
+            .unwrap()
+            .mul(&Polynomial::constant(3))
+            .unwrap()
+            .add(&x.mul(&Polynomial::constant(-2)).unwrap())
+            .unwrap()
+            .add(&Polynomial::constant(-5))
+            .unwrap();
+        assert_eq!(p.to_string(), "3*x*y - 2*x - 5");
+        assert_eq!(p.degree(), 2);
+        //Linear in y: x=1 gives 3y - 7, and x=7 gives 21y - 19
+        assert_eq!(
+            p.solve(2, &[("x", 0..10), ("y", 0..10)]),
+            Some(vec![vec![1, 3], vec![7, 1]])
+        );
+        assert_eq!(p.solve(2, &[("x", 0..10)]), None);
+        let square = x.mul(&x).unwrap();
+        assert_eq!(
+            square.solve(49, &[("x", -10..10)]),
+            Some(vec![vec![-7], vec![7]])
+        );
+    }
+
+    #[test]
+    fn intractable() {
+        //Jumps to wherever the unknown says
+        let mut symbolic = Symbolic::new(&[1105, 1, 0, 99]).with_unknown(2, "where");
+        assert_eq!(
+            symbolic.run(),
+            Err(SymbolicError::Intractable {
+                ip: 0,
+                reason: "a jump target"
+            })
+        );
+        //Reading from an unknown address is fine until something needs the value
+        let mut symbolic = Symbolic::new(&[1, 0, 0, 7, 4, 7, 99, 0]).with_unknown(1, "a");
+        symbolic.run().unwrap();
+        assert_eq!(symbolic.outputs()[0].to_string(), "([a] + 1)");
+        assert_eq!(symbolic.outputs()[0].polynomial(), None);
+    }
+}
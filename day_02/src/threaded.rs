@@ -0,0 +1,276 @@
+use crate::error::{VmError, VmErrorKind};
+use crate::network::{route, Network, NetworkError, NetworkOutcome, Topology};
+use crate::vm::{RunState, StopReason, Vm, CHUNK};
+use crate::word::Word;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+
+//Runs every machine of a network on its own thread, with channels for wires. The wiring works just like with
+// `Network::run`, and so do the results, except for the order in which different machines' packets leave a bus.
+
+enum Message<W> {
+    Value(W),
+    //Time to go home: the network is deadlocked, or some machine crashed
+    Stop,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Running,
+    Blocked,
+    Halted,
+}
+
+//What every thread needs to agree on to tell when the network is stuck. Values are only sent with the lock held,
+// so `queued` is always exactly how many values are sitting in each machine's channel.
+struct Shared<W> {
+    states: Vec<State>,
+    queued: Vec<usize>,
+    inboxes: Vec<Sender<Message<W>>>,
+    stopping: bool,
+    //The machine that crashed first, which is the one that gets the blame
+    crashed: Option<usize>,
+}
+
+impl<W> Shared<W> {
+    //Nobody's running, and nobody who's waiting has anything to read: stop everyone who's waiting
+    fn check_deadlock(&mut self) {
+        let stuck = self
+            .states
+            .iter()
+            .zip(&self.queued)
+            .all(|(&state, &queued)| {
+                state == State::Halted || (state == State::Blocked && queued == 0)
+            });
+        if stuck && !self.stopping && self.states.contains(&State::Blocked) {
+            self.stop();
+        }
+    }
+
+    fn stop(&mut self) {
+        self.stopping = true;
+        for inbox in &self.inboxes {
+            //A machine that's already gone doesn't need telling
+            let _ = inbox.send(Message::Stop);
+        }
+    }
+}
+
+//Runs a machine like `Vm::run`, a chunk at a time, checking in between whether everyone's been told to stop.
+// None means they have.
+fn run_chunked<W: Word>(
+    vm: &mut Vm<W>,
+    shared: &Mutex<Shared<W>>,
+) -> Option<Result<RunState<W>, VmError<W>>> {
+    loop {
+        let limit = vm.step_limit;
+        let chunk = vm.steps.saturating_add(CHUNK);
+        vm.step_limit = Some(limit.map_or(chunk, |limit| limit.min(chunk)));
+        let result = vm.run();
+        vm.step_limit = limit;
+        match result {
+            Err(error) if matches!(error.kind, VmErrorKind::StepLimitExceeded(_)) => match limit {
+                Some(limit) if vm.steps >= limit => return Some(Err(error)),
+                _ if shared.lock().unwrap().stopping => return None,
+                _ => {}
+            },
+            result => return Some(result),
+        }
+    }
+}
+
+//One machine's thread: runs it until it halts, crashes, or gets told to stop
+fn run_machine<W: Word>(
+    machine: usize,
+    vm: &mut Vm<W>,
+    packet: &mut Vec<W>,
+    inbox: &Receiver<Message<W>>,
+    topology: &Topology,
+    shared: &Mutex<Shared<W>>,
+    output: &Sender<W>,
+) -> Result<(), NetworkError<W>> {
+    let count = shared.lock().unwrap().states.len();
+    loop {
+        let state = match run_chunked(vm, shared) {
+            Some(Ok(state)) => state,
+            Some(Err(error)) => {
+                let mut shared = shared.lock().unwrap();
+                shared.crashed.get_or_insert(machine);
+                shared.stop();
+                return Err(NetworkError { machine, error });
+            }
+            None => return Ok(()),
+        };
+        match state.reason {
+            StopReason::Output(value) => {
+                let mut shared = shared.lock().unwrap();
+                for (to, value) in route(topology, count, machine, packet, value) {
+                    match to {
+                        Some(to) => {
+                            shared.queued[to] += 1;
+                            let _ = shared.inboxes[to].send(Message::Value(value));
+                        }
+                        None => output.send(value).unwrap(),
+                    }
+                }
+                if shared.stopping {
+                    return Ok(());
+                }
+            }
+            StopReason::Watchpoint(_) => {}
+            StopReason::Halted => {
+                let mut shared = shared.lock().unwrap();
+                shared.states[machine] = State::Halted;
+                shared.check_deadlock();
+                return Ok(());
+            }
+            StopReason::NeedsInput => {
+                let message = match inbox.try_recv() {
+                    Ok(message) => message,
+                    Err(_) => {
+                        {
+                            let mut shared = shared.lock().unwrap();
+                            shared.states[machine] = State::Blocked;
+                            shared.check_deadlock();
+                        }
+                        //Everyone else still has our sender, so this can't fail
+                        inbox.recv().unwrap()
+                    }
+                };
+                match message {
+                    Message::Value(value) => {
+                        let mut shared = shared.lock().unwrap();
+                        shared.queued[machine] -= 1;
+                        shared.states[machine] = State::Running;
+                        vm.push_input(value);
+                    }
+                    Message::Stop => return Ok(()),
+                }
+            }
+        }
+    }
+}
+
+impl<W: Word> Network<W> {
+    //Like `run`, but every machine gets its own thread
+    pub fn run_threaded(&mut self) -> Result<NetworkOutcome, NetworkError<W>> {
+        let count = self.machines.len();
+        let (inboxes, receivers): (Vec<_>, Vec<_>) = (0..count).map(|_| channel()).unzip();
+        let shared = Mutex::new(Shared {
+            states: vec![State::Running; count],
+            queued: vec![0; count],
+            inboxes,
+            stopping: false,
+            crashed: None,
+        });
+        let (output, outputs) = channel();
+        let topology = &self.topology.clone();
+
+        //Each thread hands back its inbox when it's done, so that nothing sent its way gets lost
+        let finished: Vec<_> = std::thread::scope(|scope| {
+            let threads: Vec<_> = self
+                .machines
+                .iter_mut()
+                .zip(self.packets.iter_mut())
+                .zip(receivers)
+                .enumerate()
+                .map(|(machine, ((vm, packet), inbox))| {
+                    let (shared, output) = (&shared, output.clone());
+                    scope.spawn(move || {
+                        let result =
+                            run_machine(machine, vm, packet, &inbox, topology, shared, &output);
+                        (result, inbox)
+                    })
+                })
+                .collect();
+            threads
+                .into_iter()
+                .map(|thread| thread.join().unwrap())
+                .collect()
+        });
+        drop(output);
+        self.output.extend(outputs.try_iter());
+
+        //Whatever was sent to a machine that had already stopped goes back in its input queue
+        let mut errors = Vec::new();
+        for (vm, (result, inbox)) in self.machines.iter_mut().zip(finished) {
+            for message in inbox.try_iter() {
+                if let Message::Value(value) = message {
+                    vm.push_input(value);
+                }
+            }
+            errors.extend(result.err());
+        }
+
+        //If more than one machine crashed before the others stopped, the first one gets the blame
+        if let Some(machine) = shared.into_inner().unwrap().crashed {
+            return Err(errors
+                .into_iter()
+                .find(|error| error.machine == machine)
+                .expect("The machine that crashed has no error"));
+        }
+        let waiting: Vec<usize> = (0..count)
+            .filter(|&machine| !self.machines[machine].is_halted())
+            .collect();
+        if waiting.is_empty() {
+            Ok(NetworkOutcome::Halted)
+        } else {
+            Ok(NetworkOutcome::Deadlock { waiting })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::same_threaded;
+    use crate::network::{Network, NetworkOutcome, Topology};
+    use crate::vm::Vm;
+
+    #[test]
+    fn big_ring() {
+        //Every machine adds its index to what it's given, passing it around the ring 100 times
+        let program = vec![
+            3i64, 100, 3, 101, 3, 102, 1, 100, 102, 102, 4, 102, 1001, 101, -1, 101, 1005, 101, 4,
+            99,
+        ];
+        let machines = (0..50)
+            .map(|index| {
+                let mut vm = Vm::new(program.clone());
+                vm.push_input(index);
+                vm.push_input(100);
+                vm
+            })
+            .collect();
+        let mut network = Network::new(machines, Topology::Ring);
+        network.push_input(0, 0);
+        let network = same_threaded(network);
+        assert_eq!(network.output().last(), Some(&(100 * (0..50).sum::<i64>())));
+    }
+
+    #[test]
+    fn deadlock_and_crash() {
+        let network = Network::new(vec![Vm::new(vec![3i64, 0, 4, 0, 99]); 3], Topology::Ring);
+        let mut network = same_threaded(network);
+        network.push_input(1, 5);
+        assert_eq!(network.run_threaded(), Ok(NetworkOutcome::Halted));
+        assert_eq!(network.output(), [5]);
+
+        //Machine 1 crashes on what machine 0 sends it, while machine 2 waits on it forever
+        let machines = vec![
+            Vm::new(vec![104i64, -1, 99]),
+            Vm::new(vec![3i64, 3, 4, 0, 99]),
+            Vm::new(vec![3i64, 0, 99]),
+        ];
+        let mut network = Network::new(machines, Topology::Chain);
+        let error = network.run_threaded().unwrap_err();
+        assert_eq!(error.machine, 1);
+        //Machine 1 never stops computing, and still has to notice machine 0 crashed
+        let machines = vec![Vm::new(vec![1i64, -1, 0, 0]), Vm::new(vec![1105i64, 1, 0])];
+        let mut network = Network::new(machines, Topology::Chain);
+        assert_eq!(network.run_threaded().unwrap_err().machine, 0);
+        same_threaded(Network::new(
+            vec![Vm::new(vec![104i64, 1, 104, 7, 3, 0, 99]); 2],
+            Topology::Bus { payload: 1 },
+        ));
+    }
+}
@@ -0,0 +1,193 @@
+use crate::error::{VmError, VmErrorKind};
+use crate::io::{Input, Output};
+use crate::vm::{RunState, StepRecord, StopReason, Vm};
+use crate::word::Word;
+use std::fmt;
+use std::io::Write;
+
+//Tracing can fail because of the program, or because of wherever the trace is going
+#[derive(Debug)]
+pub enum TraceError<W> {
+    Vm(VmError<W>),
+    Io(std::io::Error),
+}
+
+impl<W> From<VmError<W>> for TraceError<W> {
+    fn from(error: VmError<W>) -> Self {
+        TraceError::Vm(error)
+    }
+}
+
+impl<W> From<std::io::Error> for TraceError<W> {
+    fn from(error: std::io::Error) -> Self {
+        TraceError::Io(error)
+    }
+}
+
+impl<W: fmt::Display> fmt::Display for TraceError<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TraceError::Vm(error) => write!(f, "{}", error),
+            TraceError::Io(error) => write!(f, "couldn't write trace: {}", error),
+        }
+    }
+}
+
+impl<W: fmt::Debug + fmt::Display> std::error::Error for TraceError<W> {}
+
+impl<W: Word> StepRecord<W> {
+    //One line of JSON, with words as plain numbers (which may be bigger than a double can hold!):
+    // {"step":0,"ip":0,"opcode":1,"mnemonic":"ADD","operands":[1,1],"reads":[5,6],"write":{"address":3,"old":3,"new":2},"next_ip":4}
+    pub fn to_json(&self) -> String {
+        let operands: Vec<String> = self.operands.iter().map(|w| w.to_string()).collect();
+        let reads: Vec<String> = self.reads.iter().map(|a| a.to_string()).collect();
+        let write = match &self.write {
+            Some(write) => format!(
+                r#"{{"address":{},"old":{},"new":{}}}"#,
+                write.address, write.old, write.new
+            ),
+            None => "null".to_string(),
+        };
+        format!(
+            r#"{{"step":{},"ip":{},"opcode":{},"mnemonic":"{}","operands":[{}],"reads":[{}],"write":{},"next_ip":{}}}"#,
+            self.step,
+            self.ip,
+            self.instruction.opcode(),
+            self.instruction.mnemonic(),
+            operands.join(","),
+            reads.join(","),
+            write,
+            self.next_ip
+        )
+    }
+}
+
+impl<W: Word> Vm<W> {
+    //Like `run`, but every executed instruction also gets a line in `trace` (see `StepRecord::to_json`)
+    pub fn run_traced(&mut self, trace: &mut impl Write) -> Result<RunState<W>, TraceError<W>> {
+        loop {
+            let (stop, record) = self.step_recorded()?;
+            if let Some(record) = record {
+                writeln!(trace, "{}", record.to_json())?;
+            }
+            if let Some(reason) = stop {
+                return Ok(RunState {
+                    reason,
+                    ip: self.ip(),
+                    steps: self.steps(),
+                });
+            }
+        }
+    }
+}
+
+//Same as `execute_with_io`, streaming a trace along the way
+pub fn execute_traced<W: Word>(
+    intcode: &mut [W],
+    input: &mut impl Input<W>,
+    output: &mut impl Output<W>,
+    trace: &mut impl Write,
+) -> Result<(), TraceError<W>> {
+    let mut vm = Vm::new(&intcode[..]);
+    let result = loop {
+        match vm.run_traced(trace) {
+            Ok(RunState {
+                reason: StopReason::Halted,
+                ..
+            }) => break Ok(()),
+            Ok(RunState {
+                reason: StopReason::Output(value),
+                ..
+            }) => output.write(value),
+            Ok(RunState {
+                reason: StopReason::Watchpoint(_),
+                ..
+            }) => {}
+            Ok(RunState {
+                reason: StopReason::NeedsInput,
+                ip,
+                ..
+            }) => match input.read() {
+                Some(value) => vm.push_input(value),
+                None => {
+                    break Err(TraceError::Vm(VmError {
+                        kind: VmErrorKind::InputExhausted,
+                        ip,
+                        words: vm.memory().fetch(ip).into_owned(),
+                    }))
+                }
+            },
+            Err(error) => break Err(error),
+        }
+    };
+    for (address, cell) in intcode.iter_mut().enumerate() {
+        *cell = vm.memory().get(address);
+    }
+    //If the program crashed, that's what matters, whatever happened to the trace
+    let flushed = trace.flush();
+    result?;
+    Ok(flushed?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::VmErrorKind;
+    use crate::trace::{execute_traced, TraceError};
+    use crate::vm::Vm;
+    use std::io::Write;
+
+    #[test]
+    fn json_lines() {
+        let mut intcode = [3i64, 9, 1002, 9, 3, 9, 4, 9, 99, 0];
+        let mut output = Vec::new();
+        let mut trace = Vec::new();
+        execute_traced(
+            &mut intcode,
+            &mut vec![7].into_iter(),
+            &mut output,
+            &mut trace,
+        )
+        .unwrap();
+        assert_eq!(output, vec![21]);
+        assert_eq!(
+            String::from_utf8(trace).unwrap(),
+            r#"{"step":0,"ip":0,"opcode":3,"mnemonic":"IN","operands":[],"reads":[],"write":{"address":9,"old":0,"new":7},"next_ip":2}
+{"step":1,"ip":2,"opcode":2,"mnemonic":"MUL","operands":[7,3],"reads":[9],"write":{"address":9,"old":7,"new":21},"next_ip":6}
+{"step":2,"ip":6,"opcode":4,"mnemonic":"OUT","operands":[21],"reads":[9],"write":null,"next_ip":8}
+{"step":3,"ip":8,"opcode":99,"mnemonic":"HLT","operands":[],"reads":[],"write":null,"next_ip":8}
+"#
+        );
+    }
+
+    #[test]
+    fn waiting_for_input_isnt_traced() {
+        let mut vm = Vm::new(vec![3i64, 0, 99]);
+        let mut trace = Vec::new();
+        vm.run_traced(&mut trace).unwrap();
+        assert!(trace.is_empty());
+    }
+
+    #[test]
+    fn crashes_come_before_trace_errors() {
+        //Takes every line, but can't flush them anywhere
+        struct Broken;
+        impl Write for Broken {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Err(std::io::Error::other("broken pipe"))
+            }
+        }
+        let result = execute_traced(
+            &mut [1i64, -1, 0, 0],
+            &mut Vec::new().into_iter(),
+            &mut Vec::new(),
+            &mut Broken,
+        );
+        match result {
+            Err(TraceError::Vm(error)) => assert_eq!(error.kind, VmErrorKind::ReadOutOfBounds(-1)),
+            other => panic!("expected the VM's error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,992 @@
+use crate::error::{VmError, VmErrorKind};
+use crate::instruction::{IntcodeInstruction, Param, ParamMode};
+use crate::io::{Input, Output};
+use crate::memory::{Memory, DENSE_LIMIT};
+use crate::word::Word;
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::ops::Range;
+
+//How many instructions to run at a time when something else might want us to stop early: searches that found
+// what they were after, or networks where another machine crashed
+pub(crate) const CHUNK: u64 = 10_000;
+
+//Why the VM handed control back to us
+#[derive(Clone, Debug, PartialEq)]
+pub enum StopReason<W> {
+    //Hit a Halt instruction. Running again won't do anything.
+    Halted,
+    //Wants to read, but the input queue is empty. Push some input and run again!
+    NeedsInput,
+    //Just produced a value. Run again to keep going.
+    Output(W),
+    //The last instruction touched some watched cells (see `Vm::watch`). Run again to keep going.
+    Watchpoint(Vec<WatchHit<W>>),
+}
+
+//What a watchpoint is looking out for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    //Only writes that actually change the value
+    Change,
+}
+
+//Some cells to keep an eye on
+#[derive(Clone, Debug, PartialEq)]
+pub struct Watchpoint {
+    pub addresses: Range<usize>,
+    pub access: Access,
+}
+
+//A watched cell being touched: which one, how, and by which instruction.
+// For reads, `old` and `new` are both the value that was read.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WatchHit<W> {
+    pub address: usize,
+    pub access: Access,
+    pub ip: usize,
+    pub instruction: IntcodeInstruction<W>,
+    pub old: W,
+    pub new: W,
+}
+
+//Where the VM stopped, and how much work it did to get there
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunState<W> {
+    pub reason: StopReason<W>,
+    pub ip: usize,
+    pub steps: u64,
+}
+
+//A cell that an instruction overwrote
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemoryWrite<W> {
+    pub address: usize,
+    pub old: W,
+    pub new: W,
+}
+
+//Everything about one executed instruction: what it was, the values its source operands resolved to
+// (in order), and what it wrote, if anything
+#[derive(Clone, Debug, PartialEq)]
+pub struct StepRecord<W> {
+    pub step: u64,
+    pub ip: usize,
+    pub instruction: IntcodeInstruction<W>,
+    pub operands: Vec<W>,
+    //The addresses that were read from, for operands that aren't immediate (in order)
+    pub reads: Vec<usize>,
+    pub write: Option<MemoryWrite<W>>,
+    //Where the IP went next
+    pub next_ip: usize,
+}
+
+//Enough to take back one executed instruction: where the machine was before it, what it overwrote,
+// and which input it consumed
+#[derive(Clone, Debug, PartialEq)]
+pub struct UndoEntry<W> {
+    pub step: u64,
+    pub ip: usize,
+    pub relative_base: i64,
+    pub write: Option<MemoryWrite<W>>,
+    pub input: Option<W>,
+}
+
+//What `step_recorded` hands back: why we stopped (if we did), and what was executed (if anything was)
+pub type RecordedStep<W> = (Option<StopReason<W>>, Option<StepRecord<W>>);
+
+//What gets collected while executing an instruction, when someone asked for a record of it
+#[derive(Clone, Debug, Default)]
+struct Recording<W> {
+    operands: Vec<W>,
+    reads: Vec<usize>,
+    write: Option<MemoryWrite<W>>,
+}
+
+//An Intcode machine that can be paused and resumed.
+// It owns its memory and an input queue, and only stops when it halts, blocks on input or outputs something.
+#[derive(Clone, Debug)]
+pub struct Vm<W = i64> {
+    pub(crate) memory: Memory<W>,
+    pub(crate) ip: usize,
+    pub(crate) relative_base: i64,
+    pub(crate) steps: u64,
+    pub(crate) halted: bool,
+    pub(crate) input: VecDeque<W>,
+    //Only filled by `run_buffered`, `run` hands outputs straight to the caller
+    pub(crate) output: VecDeque<W>,
+    pub(crate) step_limit: Option<u64>,
+    pub(crate) loop_detection: bool,
+    //With loop detection on: every (IP, relative base) we've been at since the machine's state last changed,
+    // and the step we were there at
+    seen: HashMap<(usize, i64), u64>,
+    //Only there during `step_recorded`, so that plain steps don't pay for it
+    recording: Option<Recording<W>>,
+    //With the undo log on: one entry per executed instruction, oldest first
+    pub(crate) undo: Option<Vec<UndoEntry<W>>>,
+    //With the decode cache on: the instruction decoded at each address, if it's been decoded since it was last
+    // written to. Only low addresses get cached, which is where code lives anyway.
+    decoded: Option<Vec<Option<IntcodeInstruction<W>>>>,
+    //Once a compiled program has run on the machine: which of its ops (by address) can't be used anymore, because
+    // memory there isn't what was compiled. Along with which program that is.
+    pub(crate) stale: Option<(u64, Vec<bool>)>,
+    pub(crate) watchpoints: Vec<Watchpoint>,
+    //Hits from the instruction being executed, or from one that had something else to report first
+    watch_hits: Vec<WatchHit<W>>,
+}
+
+impl<W: Word> Vm<W> {
+    pub fn new(program: impl Into<Memory<W>>) -> Self {
+        Vm {
+            memory: program.into(),
+            ip: 0,
+            relative_base: 0,
+            steps: 0,
+            halted: false,
+            input: VecDeque::new(),
+            output: VecDeque::new(),
+            step_limit: None,
+            loop_detection: false,
+            seen: HashMap::new(),
+            recording: None,
+            undo: None,
+            decoded: None,
+            stale: None,
+            watchpoints: Vec::new(),
+            watch_hits: Vec::new(),
+        }
+    }
+
+    //Stop with an error after executing this many instructions (in total, not per run)
+    pub fn with_step_limit(mut self, limit: u64) -> Self {
+        self.step_limit = Some(limit);
+        self
+    }
+
+    //Stop with an error when the program goes around a loop that doesn't change anything.
+    // Only loops without writes (or I/O) can be caught this way, so a step limit is still a good idea.
+    pub fn with_loop_detection(mut self) -> Self {
+        self.loop_detection = true;
+        self
+    }
+
+    //Keep a log of every executed instruction, so they can be taken back (see `step_back`).
+    // Costs one entry per step, so it's best kept for debugging.
+    pub fn with_undo_log(mut self) -> Self {
+        self.undo.get_or_insert_with(Vec::new);
+        self
+    }
+
+    //Remember decoded instructions, instead of decoding them again every time they run.
+    // Writes throw away whatever they overlap, so self-modifying programs still work.
+    pub fn with_decode_cache(mut self) -> Self {
+        self.decoded.get_or_insert_with(Vec::new);
+        self
+    }
+
+    //Stop (with `StopReason::Watchpoint`) after any instruction that accesses these cells that way
+    pub fn with_watchpoint(mut self, addresses: Range<usize>, access: Access) -> Self {
+        self.watch(addresses, access);
+        self
+    }
+
+    //Same as `with_watchpoint`, for a machine that's already going
+    pub fn watch(&mut self, addresses: Range<usize>, access: Access) {
+        self.watchpoints.push(Watchpoint { addresses, access });
+    }
+
+    //Removes every watchpoint that includes the address. Returns false if there wasn't any.
+    pub fn unwatch(&mut self, address: usize) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints
+            .retain(|watchpoint| !watchpoint.addresses.contains(&address));
+        self.watchpoints.len() < count
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn memory(&self) -> &Memory<W> {
+        &self.memory
+    }
+
+    //For patching the program before (or while) running it
+    pub fn memory_mut(&mut self) -> &mut Memory<W> {
+        //We can't tell what's going to change, so nothing we decoded (or saw loop around) can be trusted anymore
+        if let Some(decoded) = &mut self.decoded {
+            decoded.clear();
+        }
+        self.seen.clear();
+        self.stale = None;
+        &mut self.memory
+    }
+
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    pub fn relative_base(&self) -> i64 {
+        self.relative_base
+    }
+
+    //How many instructions were executed so far
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn push_input(&mut self, value: W) {
+        //The program can go somewhere new with it, even from a place it's been before
+        self.seen.clear();
+        self.input.push_back(value)
+    }
+
+    //Everything `run_buffered` collected so far, oldest first
+    pub fn take_output(&mut self) -> Vec<W> {
+        self.output.drain(..).collect()
+    }
+
+    //Where a parameter points to in memory.
+    // Addresses can be computed from negative words, in which case we get back the address that doesn't exist.
+    fn address(&self, param: &Param<W>) -> Result<usize, W> {
+        let offset = match param.mode {
+            ParamMode::Relative => self.relative_base,
+            _ => 0,
+        };
+        match param
+            .value
+            .to_i64()
+            .and_then(|value| value.checked_add(offset))
+        {
+            Some(address) => usize::try_from(address).map_err(|_| W::from_i64(address)),
+            None => Err(param.value.clone()),
+        }
+    }
+
+    //What a parameter means when used as an input
+    fn read(&mut self, param: &Param<W>) -> Result<W, VmErrorKind<W>> {
+        let value = match param.mode {
+            ParamMode::Immediate => param.value.clone(),
+            _ => {
+                let address = self.address(param).map_err(VmErrorKind::ReadOutOfBounds)?;
+                if let Some(recording) = &mut self.recording {
+                    recording.reads.push(address);
+                }
+                let value = self.memory.get(address);
+                if !self.watchpoints.is_empty() {
+                    self.check_watchpoints(address, false, &value, &value);
+                }
+                value
+            }
+        };
+        if let Some(recording) = &mut self.recording {
+            recording.operands.push(value.clone());
+        }
+        Ok(value)
+    }
+
+    //Destinations are never immediate (the decoder makes sure of that)
+    fn write(&mut self, param: &Param<W>, value: W) -> Result<(), VmErrorKind<W>> {
+        let address = self.address(param).map_err(VmErrorKind::WriteOutOfBounds)?;
+        if self.loop_detection && self.memory.get(address) != value {
+            self.seen.clear();
+        }
+        if !self.watchpoints.is_empty() {
+            let old = self.memory.get(address);
+            self.check_watchpoints(address, true, &old, &value);
+        }
+        if let Some(recording) = &mut self.recording {
+            recording.write = Some(MemoryWrite {
+                address,
+                old: self.memory.get(address),
+                new: value.clone(),
+            });
+        }
+        if let Some(entry) = self.undo.as_mut().and_then(|log| log.last_mut()) {
+            entry.write = Some(MemoryWrite {
+                address,
+                old: self.memory.get(address),
+                new: value.clone(),
+            });
+        }
+        self.set(address, value);
+        Ok(())
+    }
+
+    //Notes down every watchpoint an access sets off. Reads pass the value they read as both the old and new one.
+    fn check_watchpoints(&mut self, address: usize, writing: bool, old: &W, new: &W) {
+        for watchpoint in &self.watchpoints {
+            let hit = match watchpoint.access {
+                Access::Read => !writing,
+                Access::Write => writing,
+                Access::Change => writing && old != new,
+            };
+            if hit && watchpoint.addresses.contains(&address) {
+                //Nothing has been written yet, so the instruction is still there to decode
+                let instruction = IntcodeInstruction::new(&self.memory.fetch(self.ip))
+                    .expect("The instruction being executed doesn't decode");
+                self.watch_hits.push(WatchHit {
+                    address,
+                    access: watchpoint.access,
+                    ip: self.ip,
+                    instruction,
+                    old: old.clone(),
+                    new: new.clone(),
+                });
+            }
+        }
+    }
+
+    //Writes to memory, forgetting any decoded (or compiled) instruction the cell was part of
+    pub(crate) fn set(&mut self, address: usize, value: W) {
+        if let Some(decoded) = &mut self.decoded {
+            for start in overlapping(address) {
+                if let Some(entry) = decoded.get_mut(start) {
+                    *entry = None;
+                }
+            }
+        }
+        if let Some((_, stale)) = &mut self.stale {
+            if self.memory.get(address) != value {
+                for start in overlapping(address) {
+                    if let Some(entry) = stale.get_mut(start) {
+                        *entry = true;
+                    }
+                }
+            }
+        }
+        self.memory.set(address, value);
+    }
+
+    //The instruction at the IP, from the cache if we've got it
+    fn decode(&mut self) -> Result<IntcodeInstruction<W>, VmErrorKind<W>> {
+        let ip = self.ip;
+        let decoded = match &mut self.decoded {
+            Some(decoded) if ip < DENSE_LIMIT => decoded,
+            _ => return IntcodeInstruction::new(&self.memory.fetch(ip)),
+        };
+        if let Some(Some(instruction)) = decoded.get(ip) {
+            return Ok(instruction.clone());
+        }
+        let instruction = IntcodeInstruction::new(&self.memory.fetch(ip))?;
+        if decoded.len() <= ip {
+            decoded.resize(ip + 1, None);
+        }
+        decoded[ip] = Some(instruction.clone());
+        Ok(instruction)
+    }
+
+    //An error, along with where it happened
+    pub(crate) fn error(&self, kind: VmErrorKind<W>) -> VmError<W> {
+        VmError {
+            kind,
+            ip: self.ip,
+            words: self.memory.fetch(self.ip).into_owned(),
+        }
+    }
+
+    //Executes a single instruction. Returns why we should stop, if we should.
+    // On error (or when waiting for input), nothing moves: the IP still points at the culprit.
+    pub fn step(&mut self) -> Result<Option<StopReason<W>>, VmError<W>> {
+        //Hits that couldn't be reported along with an output come first
+        if !self.watch_hits.is_empty() {
+            return Ok(Some(StopReason::Watchpoint(std::mem::take(
+                &mut self.watch_hits,
+            ))));
+        }
+        if self.halted {
+            return Ok(Some(StopReason::Halted));
+        }
+        if let Some(limit) = self.step_limit {
+            if self.steps >= limit {
+                return Err(self.error(VmErrorKind::StepLimitExceeded(limit)));
+            }
+        }
+        if self.loop_detection {
+            if let Some(first) = self.seen.insert((self.ip, self.relative_base), self.steps) {
+                let period = self.steps - first;
+                return Err(self.error(VmErrorKind::InfiniteLoop { period }));
+            }
+        }
+        //The entry gets filled in as we go, and thrown away again if nothing ended up being executed
+        let steps = self.steps;
+        if let Some(log) = &mut self.undo {
+            log.push(UndoEntry {
+                step: self.steps,
+                ip: self.ip,
+                relative_base: self.relative_base,
+                write: None,
+                input: None,
+            });
+        }
+        let result = match self.execute() {
+            Ok(None) if !self.watch_hits.is_empty() => Ok(Some(StopReason::Watchpoint(
+                std::mem::take(&mut self.watch_hits),
+            ))),
+            Ok(stop) => Ok(stop),
+            Err(kind) => {
+                //The instruction didn't happen after all
+                self.watch_hits.clear();
+                Err(self.error(kind))
+            }
+        };
+        if let Some(log) = &mut self.undo {
+            if self.steps == steps {
+                log.pop();
+            }
+        }
+        result
+    }
+
+    //Takes back the last executed instruction. Outputs that were already handed out stay handed out.
+    // Returns false when there's nothing (left) to take back, or the undo log is off.
+    pub fn step_back(&mut self) -> bool {
+        let entry = match self.undo.as_mut().and_then(|log| log.pop()) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        if let Some(write) = entry.write {
+            self.set(write.address, write.old);
+        }
+        if let Some(value) = entry.input {
+            self.input.push_front(value);
+        }
+        self.ip = entry.ip;
+        self.relative_base = entry.relative_base;
+        self.steps = entry.step;
+        self.halted = false;
+        //Whatever loop detection saw (or watchpoints were waiting to report) is in the future now
+        self.seen.clear();
+        self.watch_hits.clear();
+        true
+    }
+
+    //The most recent instruction that wrote to an address, as far back as the undo log goes
+    pub fn last_write_to(&self, address: usize) -> Option<&UndoEntry<W>> {
+        self.undo
+            .as_ref()?
+            .iter()
+            .rev()
+            .find(|entry| matches!(&entry.write, Some(write) if write.address == address))
+    }
+
+    //Goes back to just before the most recent write to an address, so the IP is on the instruction that did it.
+    // Returns how many steps were taken back, or None (without moving) if there's no such write in the log.
+    pub fn back_to_write(&mut self, address: usize) -> Option<u64> {
+        let target = self.last_write_to(address)?.step;
+        let steps = self.steps - target;
+        while self.steps > target {
+            self.step_back();
+        }
+        Some(steps)
+    }
+
+    //Like `step`, but also returns a record of what the instruction did.
+    // There's no record when nothing was executed (already halted, or waiting for input).
+    pub fn step_recorded(&mut self) -> Result<RecordedStep<W>, VmError<W>> {
+        let (step, ip) = (self.steps, self.ip);
+        //Decoded beforehand, since the instruction might overwrite itself
+        let instruction = IntcodeInstruction::new(&self.memory.fetch(ip)).ok();
+        self.recording = Some(Recording::default());
+        let result = self.step();
+        let recording = self.recording.take().unwrap_or_default();
+        let stop = result?;
+        let record = match instruction {
+            Some(instruction) if self.steps > step => Some(StepRecord {
+                step,
+                ip,
+                instruction,
+                operands: recording.operands,
+                reads: recording.reads,
+                write: recording.write,
+                next_ip: self.ip,
+            }),
+            _ => None,
+        };
+        Ok((stop, record))
+    }
+
+    fn execute(&mut self) -> Result<Option<StopReason<W>>, VmErrorKind<W>> {
+        let instruction = self.decode()?;
+        //Unless we jump somewhere, the next instruction comes right after this one
+        let mut next = self.ip + instruction.size();
+        let mut stop = None;
+        let flag = |condition: bool| W::from_i64(condition as i64);
+        match instruction {
+            IntcodeInstruction::Add { lhs, rhs, dest } => {
+                let value = checked(self.read(&lhs)?.try_add(&self.read(&rhs)?))?;
+                self.write(&dest, value)?;
+            }
+            IntcodeInstruction::Mul { lhs, rhs, dest } => {
+                let value = checked(self.read(&lhs)?.try_mul(&self.read(&rhs)?))?;
+                self.write(&dest, value)?;
+            }
+            IntcodeInstruction::Input { dest } => match self.input.front().cloned() {
+                Some(value) => {
+                    //Only consume the input once it's safely stored
+                    self.write(&dest, value)?;
+                    let value = self.input.pop_front();
+                    if let Some(entry) = self.undo.as_mut().and_then(|log| log.last_mut()) {
+                        entry.input = value;
+                    }
+                    self.seen.clear();
+                }
+                None => {
+                    //We'll be back here once there's input, and that's not a loop
+                    self.seen.remove(&(self.ip, self.relative_base));
+                    return Ok(Some(StopReason::NeedsInput));
+                }
+            },
+            IntcodeInstruction::Output { src } => {
+                self.seen.clear();
+                stop = Some(StopReason::Output(self.read(&src)?));
+            }
+            IntcodeInstruction::JumpIfTrue { cond, target } => {
+                if !self.read(&cond)?.is_zero() {
+                    next = jump_target(self.read(&target)?)?;
+                }
+            }
+            IntcodeInstruction::JumpIfFalse { cond, target } => {
+                if self.read(&cond)?.is_zero() {
+                    next = jump_target(self.read(&target)?)?;
+                }
+            }
+            IntcodeInstruction::LessThan { lhs, rhs, dest } => {
+                let value = flag(self.read(&lhs)? < self.read(&rhs)?);
+                self.write(&dest, value)?;
+            }
+            IntcodeInstruction::Equals { lhs, rhs, dest } => {
+                let value = flag(self.read(&lhs)? == self.read(&rhs)?);
+                self.write(&dest, value)?;
+            }
+            IntcodeInstruction::AdjustBase { offset } => {
+                let offset = self.read(&offset)?.to_i64().ok_or(VmErrorKind::Overflow)?;
+                self.relative_base = self
+                    .relative_base
+                    .checked_add(offset)
+                    .ok_or(VmErrorKind::Overflow)?;
+            }
+            IntcodeInstruction::Halt => {
+                //The IP stays on the Halt, which is where we stopped
+                self.halted = true;
+                next = self.ip;
+                stop = Some(StopReason::Halted);
+            }
+        }
+        self.ip = next;
+        self.steps += 1;
+        Ok(stop)
+    }
+
+    //Runs until the program halts, needs input, or outputs something
+    pub fn run(&mut self) -> Result<RunState<W>, VmError<W>> {
+        loop {
+            if let Some(reason) = self.step()? {
+                return Ok(RunState {
+                    reason,
+                    ip: self.ip,
+                    steps: self.steps,
+                });
+            }
+        }
+    }
+
+    //Like `run`, but watchpoint hits go to `on_hit` instead of stopping us, unless it returns true
+    pub fn run_watched(
+        &mut self,
+        mut on_hit: impl FnMut(&WatchHit<W>) -> bool,
+    ) -> Result<RunState<W>, VmError<W>> {
+        loop {
+            let state = self.run()?;
+            match &state.reason {
+                //Every hit gets its call, even after one of them asked to stop
+                StopReason::Watchpoint(hits) => {
+                    if hits.iter().fold(false, |stop, hit| on_hit(hit) | stop) {
+                        return Ok(state);
+                    }
+                }
+                _ => return Ok(state),
+            }
+        }
+    }
+
+    //Like `run`, but outputs pile up in the output queue (see `take_output`) instead of stopping us
+    pub fn run_buffered(&mut self) -> Result<RunState<W>, VmError<W>> {
+        loop {
+            let state = self.run()?;
+            match state.reason {
+                StopReason::Output(value) => self.output.push_back(value),
+                _ => return Ok(state),
+            }
+        }
+    }
+
+    //Runs to completion, getting input from `input` whenever the queue runs dry, and sending outputs to `output`
+    pub fn run_with_io(
+        &mut self,
+        input: &mut impl Input<W>,
+        output: &mut impl Output<W>,
+    ) -> Result<RunState<W>, VmError<W>> {
+        loop {
+            let state = self.run()?;
+            match state.reason {
+                StopReason::Halted => return Ok(state),
+                StopReason::Output(value) => output.write(value),
+                StopReason::Watchpoint(_) => {}
+                StopReason::NeedsInput => match input.read() {
+                    Some(value) => self.push_input(value),
+                    None => return Err(self.error(VmErrorKind::InputExhausted)),
+                },
+            }
+        }
+    }
+}
+
+fn jump_target<W: Word>(target: W) -> Result<usize, VmErrorKind<W>> {
+    target
+        .to_i64()
+        .and_then(|target| usize::try_from(target).ok())
+        .ok_or(VmErrorKind::InvalidJump(target))
+}
+
+//Each word type has exactly one meaning for arithmetic: either the result fits, or we stop right there
+fn checked<W>(result: Option<W>) -> Result<W, VmErrorKind<W>> {
+    result.ok_or(VmErrorKind::Overflow)
+}
+
+//Where every instruction that includes a cell can start. Instructions are at most 4 words long, so only the
+// 3 cells before can reach it.
+pub(crate) fn overlapping(address: usize) -> std::ops::RangeInclusive<usize> {
+    address.saturating_sub(3)..=address
+}
+
+//Runs a program in place. Anything it writes past the end of the slice is scratch space, and gets thrown away.
+// Whatever happened to the slice before an error is kept.
+pub fn execute_with_io<W: Word>(
+    intcode: &mut [W],
+    input: &mut impl Input<W>,
+    output: &mut impl Output<W>,
+) -> Result<(), VmError<W>> {
+    let mut vm = Vm::new(&intcode[..]);
+    let result = vm.run_with_io(input, output);
+    for (address, cell) in intcode.iter_mut().enumerate() {
+        *cell = vm.memory.get(address);
+    }
+    result.map(|_| ())
+}
+
+//For programs that don't do any I/O. Outputs are thrown away.
+pub fn execute<W: Word>(intcode: &mut [W]) -> Result<(), VmError<W>> {
+    execute_with_io(intcode, &mut VecDeque::new(), &mut VecDeque::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bigint::BigInt;
+    use crate::error::{VmError, VmErrorKind};
+    use crate::fixtures::{AROUND_8, REWRITES_ITSELF};
+    use crate::vm::{Access, RunState, StopReason, Vm, WatchHit};
+    use std::collections::VecDeque;
+
+    #[test]
+    fn part1_ex1() {
+        let mut intcode = [1, 0, 0, 0, 99];
+        crate::execute(&mut intcode).unwrap();
+        //Oof.
+        // - Get an iterator over that array (by reference, since we still own it!),
+        // - map() each i64 to a String,
+        // - collect the values into a Vec (it cannot be a slice, since those are borrows!)
+        // - finally, join the pieces together.
+        assert_eq!(
+            intcode
+                .iter()
+                .map(|i: &i64| i.to_string())
+                .collect::<Vec<String>>()
+                .join(","),
+            "2,0,0,0,99"
+        );
+    }
+
+    #[test]
+    fn part1_ex2() {
+        let mut intcode = [2, 3, 0, 3, 99];
+        crate::execute(&mut intcode).unwrap();
+        assert_eq!(
+            intcode
+                .iter()
+                .map(|i: &i64| i.to_string())
+                .collect::<Vec<String>>()
+                .join(","),
+            "2,3,0,6,99"
+        );
+    }
+
+    #[test]
+    fn part1_ex3() {
+        let mut intcode = [2, 4, 4, 5, 99, 0];
+        crate::execute(&mut intcode).unwrap();
+        assert_eq!(
+            intcode
+                .iter()
+                .map(|i: &i64| i.to_string())
+                .collect::<Vec<String>>()
+                .join(","),
+            "2,4,4,5,99,9801"
+        );
+    }
+
+    #[test]
+    fn part1_ex4() {
+        let mut intcode = [1, 1, 1, 4, 99, 5, 6, 0, 99];
+        crate::execute(&mut intcode).unwrap();
+        assert_eq!(
+            intcode
+                .iter()
+                .map(|i: &i64| i.to_string())
+                .collect::<Vec<String>>()
+                .join(","),
+            "30,1,1,4,2,5,6,0,99"
+        );
+    }
+
+    #[test]
+    fn immediate_operands() {
+        let mut intcode = [1002i64, 4, 3, 4, 33];
+        crate::execute(&mut intcode).unwrap();
+        assert_eq!(intcode, [1002, 4, 3, 4, 99]);
+    }
+
+    #[test]
+    fn echo() {
+        let mut intcode = [3i64, 0, 4, 0, 99];
+        let mut output = Vec::new();
+        crate::execute_with_io(&mut intcode, &mut vec![1234].into_iter(), &mut output).unwrap();
+        assert_eq!(output, vec![1234]);
+    }
+
+    #[test]
+    fn jumps_and_comparisons() {
+        let program = AROUND_8;
+        for (input, expected) in [(7, 999), (8, 1000), (9, 1001)].iter() {
+            let mut intcode = program;
+            let mut output = Vec::new();
+            crate::execute_with_io(&mut intcode, &mut vec![*input].into_iter(), &mut output)
+                .unwrap();
+            assert_eq!(output, vec![*expected]);
+        }
+    }
+
+    #[test]
+    fn memory_grows() {
+        let mut vm = Vm::new(vec![1101i64, 2, 3, 1000, 4, 1000, 99]);
+        assert_eq!(vm.run().unwrap().reason, StopReason::Output(5));
+        assert_eq!(vm.memory().get(1000), 5);
+    }
+
+    #[test]
+    fn relative_base() {
+        //Move the base to 5, then output what's 2 cells after it
+        let mut intcode = [109i64, 5, 204, 2, 99, 0, 0, 42];
+        let mut output = Vec::new();
+        crate::execute_with_io(&mut intcode, &mut VecDeque::new(), &mut output).unwrap();
+        assert_eq!(output, vec![42]);
+    }
+
+    #[test]
+    fn negative_and_large_values() {
+        //Relative offsets can be negative now
+        let mut intcode = [109i64, 10, 1201, -4, 6, 0, 99];
+        crate::execute(&mut intcode).unwrap();
+        assert_eq!(intcode[0], 105);
+
+        //That product doesn't fit in an i64, but it does in an i128 or a BigInt
+        let program = [1102i64, 34915192, 34915192, 7, 4, 7, 99, 0];
+        let mut output: Vec<i128> = Vec::new();
+        let mut intcode: Vec<i128> = program.iter().map(|&x| x as i128).collect();
+        intcode[1] = 34915192 * 1_000_000;
+        crate::execute_with_io(&mut intcode, &mut VecDeque::new(), &mut output).unwrap();
+        assert_eq!(output, vec![1219070632396864000000]);
+
+        let mut intcode: Vec<BigInt> = program.iter().map(|&x| BigInt::from(x)).collect();
+        intcode[1] = "34915192000000000000000000".parse().unwrap();
+        let mut output: Vec<BigInt> = Vec::new();
+        crate::execute_with_io(&mut intcode, &mut VecDeque::new(), &mut output).unwrap();
+        assert_eq!(output[0].to_string(), "1219070632396864000000000000000000");
+    }
+
+    #[test]
+    fn errors() {
+        let error = crate::execute(&mut [1, 0, 0, 0, 1102i64, i64::MAX, 2, 0, 99]).unwrap_err();
+        assert_eq!(
+            error,
+            VmError {
+                kind: VmErrorKind::Overflow,
+                ip: 4,
+                words: vec![1102, i64::MAX, 2, 0],
+            }
+        );
+
+        let error = crate::execute(&mut [1i64, -3, 0, 0, 99]).unwrap_err();
+        assert_eq!(error.kind, VmErrorKind::ReadOutOfBounds(-3));
+        let error = crate::execute(&mut [109i64, -10, 21101, 1, 1, 0, 99]).unwrap_err();
+        assert_eq!(error.kind, VmErrorKind::WriteOutOfBounds(-10));
+        let error = crate::execute(&mut [1105i64, 1, -1]).unwrap_err();
+        assert_eq!(error.kind, VmErrorKind::InvalidJump(-1));
+        let error = crate::execute(&mut [3i64, 0, 99]).unwrap_err();
+        assert_eq!(error.kind, VmErrorKind::InputExhausted);
+        let error = crate::execute(&mut [1i64, 0, 0, 0, 42]).unwrap_err();
+        assert_eq!((error.kind, error.ip), (VmErrorKind::UnknownOpcode(42), 4));
+        //Nothing's cut short when there's nothing left at all
+        let error = crate::execute(&mut [1101i64, 1, 1, 0]).unwrap_err();
+        assert_eq!((error.kind.clone(), error.ip), (VmErrorKind::RanOffEnd, 4));
+        assert_eq!(error.to_string(), "ran off the end of the program (ip 4)");
+    }
+
+    #[test]
+    fn resumable() {
+        //Echoes two values, one at a time
+        let mut vm = Vm::new(vec![3i64, 11, 4, 11, 3, 11, 4, 11, 99, 0, 0, 0]);
+        let state = |reason, ip, steps| RunState { reason, ip, steps };
+        assert_eq!(vm.run().unwrap(), state(StopReason::NeedsInput, 0, 0));
+        vm.push_input(5);
+        assert_eq!(vm.run().unwrap(), state(StopReason::Output(5), 4, 2));
+        assert_eq!(vm.run().unwrap(), state(StopReason::NeedsInput, 4, 2));
+        vm.push_input(6);
+        vm.push_input(7);
+        assert_eq!(vm.run_buffered().unwrap(), state(StopReason::Halted, 8, 5));
+        assert_eq!(vm.take_output(), vec![6]);
+        assert!(vm.is_halted());
+        assert_eq!(vm.run().unwrap(), state(StopReason::Halted, 8, 5));
+    }
+
+    #[test]
+    fn runaway_programs() {
+        //Jumps to itself forever
+        let mut vm = Vm::new(vec![1105i64, 1, 0]).with_loop_detection();
+        let error = vm.run().unwrap_err();
+        assert_eq!(
+            (error.kind, error.ip),
+            (VmErrorKind::InfiniteLoop { period: 1 }, 0)
+        );
+
+        //Counts up forever: every trip writes something, so only the step limit catches it
+        let program = vec![1001i64, 7, 1, 7, 1105, 1, 0, 0];
+        let mut vm = Vm::new(program).with_step_limit(100).with_loop_detection();
+        let error = vm.run().unwrap_err();
+        assert_eq!(error.kind, VmErrorKind::StepLimitExceeded(100));
+        assert_eq!(vm.memory().get(7), 50);
+
+        //Waiting for input on the same instruction isn't a loop
+        let mut vm = Vm::new(vec![3i64, 0, 1105, 1, 0]).with_loop_detection();
+        assert_eq!(vm.run().unwrap().reason, StopReason::NeedsInput);
+        assert_eq!(vm.run().unwrap().reason, StopReason::NeedsInput);
+
+        //Waits for cell 6 to change, which only patching it from outside can do
+        let mut vm = Vm::new(vec![1006i64, 6, 0, 104, 7, 99, 0]).with_loop_detection();
+        let error = vm.run().unwrap_err();
+        assert_eq!(error.kind, VmErrorKind::InfiniteLoop { period: 1 });
+        vm.memory_mut().set(6, 1);
+        assert_eq!(vm.run().unwrap().reason, StopReason::Output(7));
+    }
+
+    #[test]
+    fn undo_log() {
+        //Overwrites the same cell three times, the last time with its input
+        let mut vm =
+            Vm::new(vec![1101i64, 5, 6, 11, 1001, 11, 1, 11, 3, 11, 99, 0]).with_undo_log();
+        vm.push_input(42);
+        assert_eq!(vm.run().unwrap().reason, StopReason::Halted);
+        let entry = vm.last_write_to(11).unwrap();
+        assert_eq!((entry.step, entry.ip, entry.input), (2, 8, Some(42)));
+
+        assert_eq!(vm.back_to_write(11), Some(2));
+        assert_eq!(
+            (vm.ip(), vm.memory().get(11), vm.is_halted()),
+            (8, 12, false)
+        );
+        assert_eq!(vm.back_to_write(11), Some(1));
+        assert_eq!((vm.ip(), vm.memory().get(11)), (4, 11));
+        assert!(vm.step_back());
+        assert_eq!((vm.ip(), vm.steps(), vm.memory().get(11)), (0, 0, 0));
+        assert!(!vm.step_back());
+        assert_eq!(vm.back_to_write(11), None);
+
+        //The input went back in the queue, so running again gets us to the same place
+        assert_eq!(vm.run().unwrap().reason, StopReason::Halted);
+        assert_eq!((vm.steps(), vm.memory().get(11)), (4, 42));
+    }
+
+    #[test]
+    fn decode_cache() {
+        let program = REWRITES_ITSELF.to_vec();
+        let mut vm = Vm::new(program.clone()).with_decode_cache();
+        assert_eq!(vm.run().unwrap().reason, StopReason::Output(15));
+        let mut naive = Vm::new(program);
+        naive.run().unwrap();
+        assert_eq!(vm.memory(), naive.memory());
+
+        //Patching by hand counts too
+        let mut vm = Vm::new(vec![104i64, 1, 1105, 1, 0]).with_decode_cache();
+        assert_eq!(vm.run().unwrap().reason, StopReason::Output(1));
+        vm.memory_mut().set(1, 2);
+        assert_eq!(vm.run().unwrap().reason, StopReason::Output(2));
+    }
+
+    #[test]
+    fn watchpoints() {
+        //Writes 5 to cell 11 twice, then outputs it
+        let program = vec![1101i64, 2, 3, 11, 1101, 3, 2, 11, 4, 11, 99, 0];
+        let mut vm = Vm::new(program.clone()).with_watchpoint(11..12, Access::Change);
+        let hits = match vm.run().unwrap().reason {
+            StopReason::Watchpoint(hits) => hits,
+            reason => panic!("stopped for {:?}", reason),
+        };
+        assert_eq!(
+            hits,
+            vec![WatchHit {
+                address: 11,
+                access: Access::Change,
+                ip: 0,
+                instruction: crate::IntcodeInstruction::new(&[1101i64, 2, 3, 11]).unwrap(),
+                old: 0,
+                new: 5,
+            }]
+        );
+        assert_eq!(vm.ip(), 4);
+        //The second write doesn't change anything
+        assert_eq!(vm.run().unwrap().reason, StopReason::Output(5));
+
+        //Outputs come first, and the read that made them gets reported right after
+        let mut vm = Vm::new(program.clone()).with_watchpoint(10..12, Access::Read);
+        assert_eq!(vm.run().unwrap().reason, StopReason::Output(5));
+        let state = vm.run().unwrap();
+        assert!(matches!(&state.reason, StopReason::Watchpoint(hits) if hits[0].ip == 8));
+        assert_eq!((state.ip, state.steps), (10, 3));
+
+        //With a callback, we only stop when it says so
+        let mut vm = Vm::new(program.clone()).with_watchpoint(0..12, Access::Write);
+        let mut writers = Vec::new();
+        let state = vm.run_watched(|hit| {
+            writers.push(hit.ip);
+            false
+        });
+        assert_eq!(state.unwrap().reason, StopReason::Output(5));
+        assert_eq!(writers, vec![0, 4]);
+        assert!(vm.unwatch(3));
+        assert!(vm.watchpoints().is_empty());
+
+        //Compiled programs watch just the same
+        let compiled = crate::Compiled::new(program);
+        let mut vm = compiled.vm().with_watchpoint(11..12, Access::Write);
+        assert!(matches!(
+            compiled.run(&mut vm).unwrap().reason,
+            StopReason::Watchpoint(_)
+        ));
+    }
+}
@@ -0,0 +1,81 @@
+use crate::bigint::BigInt;
+use std::convert::TryFrom;
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+use std::str::FromStr;
+
+//What a single Intcode memory cell holds.
+// Arithmetic is checked: a result that doesn't fit the type is reported, never wrapped.
+pub trait Word:
+    Clone + Debug + Display + FromStr + Default + Eq + Ord + Hash + Send + Sync + 'static
+{
+    fn from_i64(value: i64) -> Self;
+    //None if the value doesn't fit in an i64 (only for opcodes, addresses and the like)
+    fn to_i64(&self) -> Option<i64>;
+    fn try_add(&self, rhs: &Self) -> Option<Self>;
+    fn try_mul(&self, rhs: &Self) -> Option<Self>;
+
+    fn is_zero(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Word for i64 {
+    fn from_i64(value: i64) -> Self {
+        value
+    }
+    fn to_i64(&self) -> Option<i64> {
+        Some(*self)
+    }
+    fn try_add(&self, rhs: &Self) -> Option<Self> {
+        self.checked_add(*rhs)
+    }
+    fn try_mul(&self, rhs: &Self) -> Option<Self> {
+        self.checked_mul(*rhs)
+    }
+}
+
+impl Word for i128 {
+    fn from_i64(value: i64) -> Self {
+        value as i128
+    }
+    fn to_i64(&self) -> Option<i64> {
+        i64::try_from(*self).ok()
+    }
+    fn try_add(&self, rhs: &Self) -> Option<Self> {
+        self.checked_add(*rhs)
+    }
+    fn try_mul(&self, rhs: &Self) -> Option<Self> {
+        self.checked_mul(*rhs)
+    }
+}
+
+//Never overflows, at the cost of allocating
+impl Word for BigInt {
+    fn from_i64(value: i64) -> Self {
+        BigInt::from(value)
+    }
+    fn to_i64(&self) -> Option<i64> {
+        self.to_i128().and_then(|value| i64::try_from(value).ok())
+    }
+    fn try_add(&self, rhs: &Self) -> Option<Self> {
+        Some(self + rhs)
+    }
+    fn try_mul(&self, rhs: &Self) -> Option<Self> {
+        Some(self * rhs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bigint::BigInt;
+    use crate::word::Word;
+
+    #[test]
+    fn overflow_is_reported() {
+        assert_eq!(i64::MAX.try_add(&1), None);
+        assert_eq!((i64::MAX as i128).try_add(&1), Some(i64::MAX as i128 + 1));
+        let big = BigInt::from_i64(i64::MAX);
+        assert_eq!(big.try_mul(&big).unwrap().to_i64(), None);
+    }
+}